@@ -1,3 +1,219 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, str::FromStr};
+
+use engine::{Heightmap, MaterialLayer, Materials, PresentMode, Region, Settings, VoxScene};
+
+const USAGE: &str = "Usage: d32 [command] [options]
+
+Commands:
+  run [--size W H] [--fullscreen | --windowed] [--vsync | --no-vsync] [--world PATH] [--seed N]
+      Open the engine window, the default when no command is given
+  render <output.png> [--size W H] [--world PATH] [--seed N] [--camera X Y Z] [--target X Y Z]
+      Render a single frame without a window
+  gen <output.vox|obj|ply> [--seed N] [--min X Y Z] [--max X Y Z]
+      Generate a world region and write it to disk
+  info
+      Print the graphics adapters and settings
+  export <output.vox|obj|ply> [--world PATH] [--seed N] [--input SCENE.vox] [--min X Y Z] [--max X Y Z]
+      Export a box of the world the engine runs, or of a VOX scene given with --input
+  heightmap <height.png> <output.vox|obj|ply> [--colors <color.png>] [--scale N]
+      Extrude a heightmap into voxel terrain
+  help
+      Print this message
+
+Options for every command:
+  --data-dir PATH
+      Keep settings, logs and crash reports in PATH, as does the D32_DATA_DIR environment variable.
+      A file named `portable` next to the executable keeps them in a `data` directory beside it";
+
 fn main() {
-    engine::Engine::new().start()
-}
\ No newline at end of file
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) if !command.starts_with("--") => (command.as_str(), rest),
+        _ => ("run", &args[..])
+    };
+    if matches!(command, "help" | "-h") || args.iter().any(|a| a == "--help") {
+        return println!("{USAGE}")
+    }
+    let result = Args::parse(rest).and_then(|mut args| {
+        // Before anything is logged, the log file is in the data directory
+        if let Some(dir) = args.value("--data-dir")? { engine::set_data_dir(dir) }
+        args.flags.remove("--data-dir");
+        engine::start_logger();
+        let settings = Settings::read();
+        engine::configure_logger(&settings);
+        match command {
+            "run" => run(args, settings),
+            "render" => render(args, settings),
+            "gen" => gen(args, settings),
+            "info" => info(args, settings),
+            "export" => export(args, settings),
+            "heightmap" => heightmap(args),
+            _ => Err(format!("Unknown command: {command}"))
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("{e}\nRun `d32 help` for usage");
+        std::process::exit(1)
+    }
+}
+
+fn run(args: Args, mut settings: Settings) -> Result<(), String> {
+    args.finish(0, &["--size", "--fullscreen", "--windowed", "--vsync", "--no-vsync", "--world", "--seed"])?;
+    args.apply_world(&mut settings)?;
+    if let Some([width, height]) = args.values("--size")? {
+        settings.window_size = Some([width, height]);
+        settings.window_maximized = false;
+    }
+    if args.given("--fullscreen") && args.given("--windowed") {
+        return Err("--fullscreen cannot be combined with --windowed".into())
+    }
+    if args.given("--vsync") && args.given("--no-vsync") {
+        return Err("--vsync cannot be combined with --no-vsync".into())
+    }
+    if args.switch("--fullscreen")? { settings.window_fullscreen = true }
+    if args.switch("--windowed")? { settings.window_fullscreen = false }
+    if args.switch("--vsync")? { settings.present_mode = PresentMode::Fifo }
+    if args.switch("--no-vsync")? { settings.present_mode = PresentMode::Immediate }
+    engine::Engine::with_settings(settings).map_err(|e| e.to_string())?.start().map_err(|e| e.to_string())
+}
+
+fn render(args: Args, mut settings: Settings) -> Result<(), String> {
+    args.finish(1, &["--size", "--world", "--seed", "--camera", "--target"])?;
+    let output = Path::new(&args.positional[0]);
+    args.apply_world(&mut settings)?;
+    let [width, height] = args.values("--size")?.unwrap_or([1280, 720]);
+    let camera = match (args.values("--camera")?, args.values("--target")?) {
+        (Some(position), Some(target)) => Some((position, target)),
+        (None, None) => None,
+        _ => return Err("--camera and --target must be given together".into())
+    };
+    engine::render_image(&settings, [width.max(1), height.max(1)], camera, output)
+        .map_err(|e| format!("Failed to render to {output:?}: {e}"))?;
+    log::info!("Rendered {width}x{height} frame to {output:?}");
+    Ok(())
+}
+
+fn gen(args: Args, settings: Settings) -> Result<(), String> {
+    args.finish(1, &["--seed", "--min", "--max"])?;
+    let output = Path::new(&args.positional[0]);
+    let seed = args.value("--seed")?.unwrap_or(settings.seed);
+    let region = Region::new(
+        args.values("--min")?.unwrap_or(engine::WORLD_REGION.min),
+        args.values("--max")?.unwrap_or(engine::WORLD_REGION.max)
+    );
+    let mut materials = Materials::new();
+    let chunks = engine::generate_world(seed, region, &mut materials);
+    engine::export_region(output, &chunks, &materials, region).map_err(|e| format!("Failed to write {output:?}: {e}"))?;
+    log::info!("Generated {region:?} with seed {seed} into {} chunks at {output:?}", chunks.len());
+    Ok(())
+}
+
+fn info(args: Args, settings: Settings) -> Result<(), String> {
+    args.finish(0, &[])?;
+    let adapters = engine::adapters(&settings);
+    if adapters.is_empty() { println!("No graphics adapters found") }
+    for adapter in adapters {
+        println!("Adapter: {} ({:?}, {:?}, driver {} {})", adapter.name, adapter.backend, adapter.device_type,
+            adapter.driver, adapter.driver_info);
+    }
+    let dirs = engine::data_dirs();
+    println!("Directories ({:?}): config {:?}, data {:?}, logs {:?}", dirs.source, dirs.config, dirs.data, dirs.cache);
+    let settings = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+    println!("Settings: {settings}");
+    Ok(())
+}
+
+fn export(args: Args, mut settings: Settings) -> Result<(), String> {
+    args.finish(1, &["--world", "--seed", "--input", "--min", "--max"])?;
+    if args.given("--input") && (args.given("--world") || args.given("--seed")) {
+        return Err("--input cannot be combined with --world or --seed".into())
+    }
+    let output = Path::new(&args.positional[0]);
+    let mut materials = Materials::new();
+    let chunks = match args.value::<PathBuf>("--input")? {
+        Some(input) => VoxScene::read(&input).map_err(|e| format!("Failed to read {input:?}: {e}"))?
+            .to_chunks([0;3], &mut materials),
+        None => {
+            args.apply_world(&mut settings)?;
+            engine::load_world(&settings, &mut materials)
+        }
+    };
+    let bounds = Region::of_chunks(&chunks).ok_or("The world contains no voxels")?;
+    let region = Region::new(args.values("--min")?.unwrap_or(bounds.min), args.values("--max")?.unwrap_or(bounds.max));
+    engine::export_region(output, &chunks, &materials, region).map_err(|e| format!("Failed to export to {output:?}: {e}"))?;
+    log::info!("Exported {region:?} to {output:?}");
+    Ok(())
+}
+
+fn heightmap(args: Args) -> Result<(), String> {
+    args.finish(2, &["--colors", "--scale"])?;
+    let (input, output) = (Path::new(&args.positional[0]), Path::new(&args.positional[1]));
+    let mut heightmap = Heightmap::read(input, args.value("--scale")?.unwrap_or(32.))
+        .map_err(|e| format!("Failed to read {input:?}: {e}"))?;
+    if let Some(colors) = args.value::<PathBuf>("--colors")? {
+        heightmap.read_colors(&colors).map_err(|e| format!("Failed to read {colors:?}: {e}"))?;
+    }
+    let mut materials = Materials::new();
+    let chunks = heightmap.to_chunks([0;3], &MaterialLayer::defaults(), &mut materials);
+    let region = Region::of_chunks(&chunks).ok_or_else(|| format!("{input:?} is empty"))?;
+    engine::export_region(output, &chunks, &materials, region).map_err(|e| format!("Failed to export to {output:?}: {e}"))?;
+    log::info!("Imported {input:?} into {} chunks and exported to {output:?}", chunks.len());
+    Ok(())
+}
+
+// Positional arguments followed by `--flag value...` options, each flag taking every value up to the next flag
+struct Args {
+    positional: Vec<String>,
+    flags: HashMap<String, Vec<String>>
+}
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut positional = vec![];
+        let mut flags: HashMap<String, Vec<String>> = HashMap::new();
+        let mut current = None;
+        for arg in args {
+            if arg.starts_with("--") {
+                if flags.insert(arg.clone(), vec![]).is_some() { return Err(format!("{arg} given more than once")) }
+                current = Some(arg.clone());
+            } else if let Some(flag) = &current {
+                flags.get_mut(flag).unwrap().push(arg.clone());
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        Ok(Self { positional, flags })
+    }
+    fn finish(&self, positional: usize, known: &[&str]) -> Result<(), String> {
+        if self.positional.len() != positional {
+            return Err(format!("Expected {positional} arguments, got {}", self.positional.len()))
+        }
+        match self.flags.keys().find(|flag| !known.contains(&flag.as_str())) {
+            Some(flag) => Err(format!("Unknown option: {flag}")),
+            None => Ok(())
+        }
+    }
+    fn given(&self, flag: &str) -> bool {
+        self.flags.contains_key(flag)
+    }
+    // A flag without values, which would otherwise swallow the positional arguments after it
+    fn switch(&self, flag: &str) -> Result<bool, String> {
+        match self.flags.get(flag).map(Vec::as_slice) {
+            Some([value, ..]) => Err(format!("{flag} takes no value, got {value}")),
+            values => Ok(values.is_some())
+        }
+    }
+    fn values<T: FromStr, const N: usize>(&self, flag: &str) -> Result<Option<[T;N]>, String> {
+        let Some(values) = self.flags.get(flag) else { return Ok(None) };
+        let parsed: Vec<T> = values.iter().map(|v| v.parse().map_err(|_| format!("Invalid value for {flag}: {v}")))
+            .collect::<Result<_, _>>()?;
+        parsed.try_into().map(Some).map_err(|_| format!("{flag} expects {N} values"))
+    }
+    fn value<T: FromStr>(&self, flag: &str) -> Result<Option<T>, String> {
+        Ok(self.values::<T, 1>(flag)?.map(|[v]| v))
+    }
+    fn apply_world(&self, settings: &mut Settings) -> Result<(), String> {
+        if let Some(path) = self.value("--world")? { settings.world_path = Some(path) }
+        if let Some(seed) = self.value("--seed")? { settings.seed = seed }
+        Ok(())
+    }
+}
@@ -2,7 +2,9 @@ use std::sync::Mutex;
 
 use cgmath::{Vector3, InnerSpace};
 use wgpu::{util::DeviceExt, Queue};
-use winit::{window::Window, dpi::PhysicalSize};
+use winit::dpi::PhysicalSize;
+
+use crate::{Debug, Layouts};
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -11,6 +13,7 @@ pub struct CameraBinding {
     pub centre: [f32;4],
     pub u: [f32;4],
     pub v: [f32;4],
+    pub screen: [f32;4],
 }
 
 #[derive(Clone)]
@@ -25,7 +28,8 @@ pub struct CameraValues {
     pub alignment: Vector3<f32>,
     pub u: Vector3<f32>,
     pub v: Vector3<f32>,
-    pub centre: Vector3<f32>
+    pub centre: Vector3<f32>,
+    pub screen: PhysicalSize<u32>
 }
 impl CameraValues {
     pub fn update(&mut self) {
@@ -37,13 +41,15 @@ impl CameraValues {
         self.v *= self.horizontal_size / self.aspect_ratio;
     }
 }
-impl Into<CameraBinding> for CameraValues {
-    fn into(self) -> CameraBinding {
-        CameraBinding {
-            position: self.position.extend(1.).into(),
-            centre: self.centre.extend(1.).into(),
-            u: self.u.extend(1.).into(),
-            v: self.v.extend(1.).into()
+impl From<CameraValues> for CameraBinding {
+    fn from(values: CameraValues) -> Self {
+        let (w, h) = (values.screen.width.max(1) as f32, values.screen.height.max(1) as f32);
+        Self {
+            position: values.position.extend(1.).into(),
+            centre: values.centre.extend(1.).into(),
+            u: values.u.extend(1.).into(),
+            v: values.v.extend(1.).into(),
+            screen: [w, h, 1. / w, 1. / h]
         }
     }
 }
@@ -57,36 +63,43 @@ pub struct Camera {
 impl Camera {
     pub fn new(
         device: &wgpu::Device,
-        window: &Window,
-        raytrace_shader: &wgpu::RenderPipeline
+        layouts: &Layouts,
+        screen: PhysicalSize<u32>,
+        fov: f32,
+        debug: &Debug
     ) -> Self {
         let values = CameraValues {
             position: [0., -2., 0.].into(),
             lookat: [0.;3].into(),
             up: [0., 0., 1.].into(),
             length: 1.,
-            horizontal_size: 1.,
-            aspect_ratio: 1.,
+            horizontal_size: horizontal_size(fov, 1.),
+            aspect_ratio: screen.width.max(1) as f32 / screen.height.max(1) as f32,
 
             alignment: [0.;3].into(),
             centre: [0.;3].into(),
             u: [0.;3].into(),
-            v: [0.;3].into()
+            v: [0.;3].into(),
+            screen
         };
         let buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: None,
-                contents: bytemuck::cast_slice(&[ CameraBinding::from(values.clone().into()) ]),
+                contents: bytemuck::cast_slice(&[ CameraBinding::from(values.clone()) ]),
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
             }
         );
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
-            layout: &raytrace_shader.get_bind_group_layout(0),
+            layout: &layouts.camera,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
                     resource: buffer.as_entire_binding()
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: debug.buffer.as_entire_binding()
                 }
             ]
         });
@@ -99,8 +112,24 @@ impl Camera {
     pub fn update(&self, queue: &Queue) {
         let mut values = self.values.lock().unwrap();
         values.update();
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[ CameraBinding::from(values.clone().into()) ]))
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[ CameraBinding::from(values.clone()) ]))
+    }
+    pub fn set_fov(&self, fov: f32) {
+        let mut values = self.values.lock().unwrap();
+        values.horizontal_size = horizontal_size(fov, values.length);
+    }
+    pub fn look_at(&self, position: [f32;3], target: [f32;3]) {
+        let mut values = self.values.lock().unwrap();
+        values.position = position.into();
+        values.lookat = target.into();
     }
     pub fn resize(&self, new_size: PhysicalSize<u32>) {
+        let mut values = self.values.lock().unwrap();
+        values.aspect_ratio = new_size.width.max(1) as f32 / new_size.height.max(1) as f32;
+        values.screen = new_size;
     }
-}
\ No newline at end of file
+}
+// Half the width of the image plane at `length` in front of the camera for a horizontal field of view in degrees
+fn horizontal_size(fov: f32, length: f32) -> f32 {
+    (fov.to_radians() / 2.).tan() * length
+}
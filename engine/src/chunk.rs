@@ -1,15 +1,59 @@
+pub const CHUNK_SIZE: usize = 16;
+
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Chunk {
     pub position: [f32;4],
-    pub data: [[[u32;16];16];16]
+    pub data: [[[u32;16];16];16],
+    pub lod1: [[[u32;8];8];8],
+    pub lod2: [[[u32;4];4];4],
+    pub lod3: [[[u32;2];2];2]
 }
 
 impl Chunk {
     pub fn new() -> Self {
         Self {
             position: [0.;4],
-            data: [[[0;16];16];16]
+            data: [[[0;16];16];16],
+            lod1: [[[0;8];8];8],
+            lod2: [[[0;4];4];4],
+            lod3: [[[0;2];2];2]
+        }
+    }
+    pub fn generate_lods(&mut self) {
+        downsample(&self.data, &mut self.lod1);
+        downsample(&self.lod1, &mut self.lod2);
+        downsample(&self.lod2, &mut self.lod3);
+    }
+}
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Each parent cell keeps the most common material of its 2x2x2 children, or stays empty when
+// fewer than half of them are solid
+fn downsample<const N: usize, const M: usize>(src: &[[[u32;N];N];N], dst: &mut [[[u32;M];M];M]) {
+    for (x, plane) in dst.iter_mut().enumerate() {
+        for (y, row) in plane.iter_mut().enumerate() {
+            for (z, cell) in row.iter_mut().enumerate() {
+                let mut children = [0u32;8];
+                for (i, child) in children.iter_mut().enumerate() {
+                    *child = src[x * 2 + (i & 1)][y * 2 + ((i >> 1) & 1)][z * 2 + (i >> 2)];
+                }
+                *cell = majority(&children);
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+fn majority(children: &[u32;8]) -> u32 {
+    if children.iter().filter(|m| **m != 0).count() < 4 { return 0 }
+    let mut best = (0, 0);
+    for material in children.iter().copied().filter(|m| *m != 0) {
+        let count = children.iter().filter(|m| **m == material).count();
+        if count > best.1 { best = (material, count) }
+    }
+    best.0
+}
@@ -1,64 +1,224 @@
-use std::sync::Mutex;
+use std::sync::{Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}};
 use wgpu::util::DeviceExt;
 
-use crate::Chunk;
+use crate::{Chunk, Layouts, Materials, Octree, Settings, VoxScene};
 
 pub struct Chunks {
-    pub current_length: usize,
-    pub maximum_length: usize,
+    pub current_length: AtomicUsize,
+    pub maximum_length: AtomicUsize,
     pub length_buffer: wgpu::Buffer,
+    pub lod_distance: u32,
+    // Whether loaded worlds become the static octree rather than chunks
+    pub use_octree: bool,
 
-    pub chunks: Vec<Chunk>,
+    pub chunks: Mutex<Vec<Chunk>>,
     pub chunks_buffer: Mutex<wgpu::Buffer>,
+    pub dirty: AtomicBool,
+
+    pub octree: Mutex<Octree>,
+    pub octree_dirty: AtomicBool,
+    pub octree_buffer: Mutex<wgpu::Buffer>,
+    pub octree_info_buffer: wgpu::Buffer,
+
+    pub materials: Mutex<Materials>,
+    pub materials_buffer: Mutex<wgpu::Buffer>,
 
     pub bind_group: Mutex<wgpu::BindGroup>,
 }
 impl Chunks {
     pub fn new(
         device: &wgpu::Device,
-        raytrace_shader: &wgpu::RenderPipeline
+        layouts: &Layouts,
+        settings: &Settings
     ) -> Self {
+        let lod_distance = settings.lod_distance.max(0.) as u32;
         let length_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: None,
-                contents: bytemuck::cast_slice(&[0u32;4]),
+                contents: bytemuck::cast_slice(&[0, lod_distance, 0, 0]),
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
             }
         );
-        let chunks_buffer = device.create_buffer_init(
+        let chunks_buffer = create_chunks_buffer(device, 1);
+        let octree = Octree::empty();
+        let octree_buffer = create_octree_buffer(device, &octree);
+        let octree_info_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: None,
-                contents: bytemuck::cast_slice(&vec![
-                    Chunk::new()
-                ]),
-                usage: wgpu::BufferUsages::STORAGE
+                contents: bytemuck::cast_slice(&octree.info()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
             }
         );
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &raytrace_shader.get_bind_group_layout(1),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: chunks_buffer.as_entire_binding()
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: length_buffer.as_entire_binding()
-                }
-            ]
-        });
+        let materials = Materials::new();
+        let materials_buffer = create_materials_buffer(device, &materials);
+        let bind_group = create_bind_group(device, layouts, &chunks_buffer, &length_buffer,
+            &octree_buffer, &octree_info_buffer, &materials_buffer);
         Self {
-            current_length: 0,
-            maximum_length: 0,
+            current_length: AtomicUsize::new(0),
+            maximum_length: AtomicUsize::new(1),
             length_buffer,
+            lod_distance,
+            use_octree: settings.octree_world,
 
             chunks_buffer: Mutex::new(chunks_buffer),
-            chunks: vec![],
-            
+            chunks: Mutex::new(vec![]),
+            dirty: AtomicBool::new(false),
+
+            octree: Mutex::new(octree),
+            octree_dirty: AtomicBool::new(false),
+            octree_buffer: Mutex::new(octree_buffer),
+            octree_info_buffer,
+
+            materials: Mutex::new(materials),
+            materials_buffer: Mutex::new(materials_buffer),
+
             bind_group: Mutex::new(bind_group)
         }
     }
-    pub fn update(&self) {
+    pub fn push(&self, mut chunk: Chunk) {
+        chunk.generate_lods();
+        self.chunks.lock().unwrap().push(chunk);
+        self.dirty.store(true, Ordering::Relaxed)
+    }
+    pub fn extend(&self, chunks: impl IntoIterator<Item = Chunk>) {
+        let mut current = self.chunks.lock().unwrap();
+        for mut chunk in chunks {
+            chunk.generate_lods();
+            current.push(chunk);
+        }
+        self.dirty.store(true, Ordering::Relaxed)
+    }
+    /// Removes the chunks and the octree.
+    pub fn clear(&self) {
+        self.chunks.lock().unwrap().clear();
+        self.dirty.store(true, Ordering::Relaxed);
+        self.set_octree(Octree::empty());
+    }
+    /// Adds a static world, built into the octree when `octree_world` is set and otherwise added as chunks.
+    pub fn load_world(&self, world: Vec<Chunk>) {
+        if self.use_octree {
+            self.set_octree(Octree::from_chunks(&world));
+            // The materials are uploaded with the chunks
+            self.dirty.store(true, Ordering::Relaxed);
+        } else {
+            self.extend(world);
+        }
+    }
+    pub fn load_vox(&self, scene: &VoxScene, offset: [i32;3]) {
+        let chunks = scene.to_chunks(offset, &mut self.materials.lock().unwrap());
+        log::info!("Loaded {} chunks from {} VOX models at {offset:?}", chunks.len(), scene.models.len());
+        self.load_world(chunks)
+    }
+    /// Replaces the octree, uploaded on the next update.
+    pub fn set_octree(&self, octree: Octree) {
+        *self.octree.lock().unwrap() = octree;
+        self.octree_dirty.store(true, Ordering::Relaxed);
+    }
+    fn rebuild_bind_group(&self, device: &wgpu::Device, layouts: &Layouts) {
+        *self.bind_group.lock().unwrap() = create_bind_group(device, layouts, &self.chunks_buffer.lock().unwrap(),
+            &self.length_buffer, &self.octree_buffer.lock().unwrap(), &self.octree_info_buffer,
+            &self.materials_buffer.lock().unwrap());
+    }
+    pub fn update(&self, device: &wgpu::Device, queue: &wgpu::Queue, layouts: &Layouts) {
+        if self.octree_dirty.swap(false, Ordering::Relaxed) {
+            let octree = self.octree.lock().unwrap();
+            *self.octree_buffer.lock().unwrap() = create_octree_buffer(device, &octree);
+            self.rebuild_bind_group(device, layouts);
+            queue.write_buffer(&self.octree_info_buffer, 0, bytemuck::cast_slice(&octree.info()));
+        }
+        if !self.dirty.swap(false, Ordering::Relaxed) { return }
+        let chunks = self.chunks.lock().unwrap();
+        let length = chunks.len();
+        if length > self.maximum_length.load(Ordering::Relaxed) {
+            let maximum_length = length.next_power_of_two();
+            *self.chunks_buffer.lock().unwrap() = create_chunks_buffer(device, maximum_length);
+            self.rebuild_bind_group(device, layouts);
+            self.maximum_length.store(maximum_length, Ordering::Relaxed);
+            log::trace!("Chunks buffer grown to {maximum_length} chunks");
+        }
+        if length > 0 {
+            queue.write_buffer(&self.chunks_buffer.lock().unwrap(), 0, bytemuck::cast_slice(&chunks));
+        }
+        queue.write_buffer(&self.length_buffer, 0, bytemuck::cast_slice(&[length as u32, self.lod_distance, 0, 0]));
+        self.current_length.store(length, Ordering::Relaxed);
+
+        let materials = self.materials.lock().unwrap().packed();
+        let materials_size = std::mem::size_of_val(materials.as_slice()) as u64;
+        if materials_size > self.materials_buffer.lock().unwrap().size() {
+            *self.materials_buffer.lock().unwrap() = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Materials"),
+                size: materials_size.next_power_of_two(),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false
+            });
+            self.rebuild_bind_group(device, layouts);
+        }
+        queue.write_buffer(&self.materials_buffer.lock().unwrap(), 0, bytemuck::cast_slice(&materials));
     }
-}
\ No newline at end of file
+}
+
+fn create_chunks_buffer(device: &wgpu::Device, length: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Chunks"),
+        size: (length.max(1) * std::mem::size_of::<Chunk>()) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false
+    })
+}
+
+fn create_octree_buffer(device: &wgpu::Device, octree: &Octree) -> wgpu::Buffer {
+    device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Octree"),
+            contents: bytemuck::cast_slice(&octree.nodes),
+            usage: wgpu::BufferUsages::STORAGE
+        }
+    )
+}
+
+fn create_materials_buffer(device: &wgpu::Device, materials: &Materials) -> wgpu::Buffer {
+    device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Materials"),
+            contents: bytemuck::cast_slice(&materials.packed()),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST
+        }
+    )
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layouts: &Layouts,
+    chunks_buffer: &wgpu::Buffer,
+    length_buffer: &wgpu::Buffer,
+    octree_buffer: &wgpu::Buffer,
+    octree_info_buffer: &wgpu::Buffer,
+    materials_buffer: &wgpu::Buffer
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &layouts.chunks,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: chunks_buffer.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: length_buffer.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: octree_buffer.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: octree_info_buffer.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: materials_buffer.as_entire_binding()
+            }
+        ]
+    })
+}
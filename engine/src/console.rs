@@ -0,0 +1,225 @@
+use std::{collections::{BTreeMap, BTreeSet}, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}};
+
+use log::Level;
+use winit::dpi::PhysicalSize;
+
+use crate::{utils, world, Context, Settings, Ui, GLYPH_CELL};
+
+pub const HISTORY_LENGTH: usize = 64;
+
+pub type CommandHandler = dyn Fn(&Context, &[&str]) -> Result<(), String> + Send + Sync;
+
+pub struct Command {
+    pub usage: String,
+    pub description: String,
+    handler: Box<CommandHandler>
+}
+
+/// An in-game console, toggled with the backtick key, showing the recent log and running registered
+/// commands. Commands report through the log and return an error message when they fail.
+pub struct Console {
+    pub open: AtomicBool,
+    input: Mutex<String>,
+    history: Mutex<Vec<String>>,
+    // Position while browsing the history, from the most recent entry
+    history_position: Mutex<Option<usize>>,
+    commands: Mutex<BTreeMap<String, Arc<Command>>>,
+    // Settings changed through commands, the only ones `save` writes
+    changed_settings: Mutex<BTreeSet<String>>
+}
+impl Console {
+    pub fn new() -> Self {
+        let console = Self {
+            open: AtomicBool::new(false),
+            input: Mutex::new(String::new()),
+            history: Mutex::new(vec![]),
+            history_position: Mutex::new(None),
+            commands: Mutex::new(BTreeMap::new()),
+            changed_settings: Mutex::new(BTreeSet::new())
+        };
+        register_builtins(&console);
+        console
+    }
+    /// Adds a command, replacing any with the same name. `usage` lists the arguments, as in `tp <x> <y> <z>`.
+    pub fn register(
+        &self,
+        name: &str,
+        usage: &str,
+        description: &str,
+        handler: impl Fn(&Context, &[&str]) -> Result<(), String> + Send + Sync + 'static
+    ) {
+        self.commands.lock().unwrap().insert(name.into(), Arc::new(Command {
+            usage: usage.into(),
+            description: description.into(),
+            handler: Box::new(handler)
+        }));
+    }
+    pub fn toggle(&self) {
+        self.open.fetch_xor(true, Ordering::Relaxed);
+    }
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+    /// Handles a character typed while the console is open, running the input on enter.
+    pub fn input_char(&self, c: &Context, character: char) {
+        match character {
+            '\r' | '\n' => {
+                let line = std::mem::take(&mut *self.input.lock().unwrap());
+                *self.history_position.lock().unwrap() = None;
+                if line.trim().is_empty() { return }
+                self.push_history(&line);
+                self.execute(c, &line);
+            }
+            '\u{8}' => { self.input.lock().unwrap().pop(); }
+            // The toggle key and other control characters
+            '`' => {}
+            character if character.is_control() => {}
+            character => self.input.lock().unwrap().push(character)
+        }
+    }
+    /// Adds a line to the history unless it repeats the last one, dropping the oldest past `HISTORY_LENGTH`.
+    pub fn push_history(&self, line: &str) {
+        let mut history = self.history.lock().unwrap();
+        if history.last().map(String::as_str) != Some(line) {
+            if history.len() == HISTORY_LENGTH { history.remove(0); }
+            history.push(line.into());
+        }
+    }
+    pub fn input(&self) -> String {
+        self.input.lock().unwrap().clone()
+    }
+    /// Replaces the input with an earlier (`back`) or later entry of the history.
+    pub fn browse_history(&self, back: bool) {
+        let history = self.history.lock().unwrap();
+        let mut position = self.history_position.lock().unwrap();
+        *position = match (*position, back) {
+            (None, true) if !history.is_empty() => Some(0),
+            (Some(i), true) => Some((i + 1).min(history.len() - 1)),
+            (Some(0), false) | (None, _) => None,
+            (Some(i), false) => Some(i - 1)
+        };
+        *self.input.lock().unwrap() = position.map_or(String::new(), |i| history[history.len() - 1 - i].clone());
+    }
+    pub fn execute(&self, c: &Context, line: &str) {
+        log::info!("> {line}");
+        let Some((name, args)) = parse_command(line) else { return };
+        let Some(command) = self.commands.lock().unwrap().get(name).cloned() else {
+            return log::warn!("Unknown command {name}, `help` lists them")
+        };
+        if let Err(e) = (command.handler)(c, &args) {
+            log::warn!("{e}\nUsage: {name} {}", command.usage);
+        }
+    }
+    /// The recent log over the top half of the window with the input line below it.
+    pub fn draw(&self, ui: &Ui, size: PhysicalSize<u32>) {
+        if !self.is_open() { return }
+        let [column, row] = GLYPH_CELL.map(|v| v as f32 * ui.scale);
+        let height = (size.height as f32 / 2.).max(row * 2.);
+        let columns = ((size.width as f32 - 16.) / column).max(1.) as usize;
+        let rows = (height / row) as usize - 1;
+        ui.rect([0., 0.], [size.width as f32, height + 4.], [0.05, 0.05, 0.08, 0.85]);
+        let lines = utils::recent_log(rows);
+        for (i, (level, line)) in lines.iter().enumerate() {
+            let colour = match level {
+                Level::Error => [1., 0.35, 0.3, 1.],
+                Level::Warn => [1., 0.85, 0.3, 1.],
+                Level::Info => [0.9, 0.9, 0.9, 1.],
+                Level::Debug | Level::Trace => [0.6, 0.6, 0.6, 1.]
+            };
+            let y = (rows - lines.len() + i) as f32 * row;
+            ui.text_coloured([8., y], colour, &line.chars().take(columns).collect::<String>());
+        }
+        let input = format!("> {}_", self.input.lock().unwrap());
+        let skip = input.chars().count().saturating_sub(columns);
+        ui.text_coloured([8., rows as f32 * row], [0.5, 1., 0.6, 1.], &input.chars().skip(skip).collect::<String>());
+    }
+}
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits a line into the command name and its arguments.
+pub fn parse_command(line: &str) -> Option<(&str, Vec<&str>)> {
+    let mut words = line.split_whitespace();
+    Some((words.next()?, words.collect()))
+}
+
+fn parse_args<const N: usize>(args: &[&str]) -> Result<[f32;N], String> {
+    if args.len() != N { return Err(format!("Expected {N} arguments, got {}", args.len())) }
+    let mut values = [0.;N];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg.parse().map_err(|_| format!("Not a number: {arg}"))?;
+    }
+    Ok(values)
+}
+
+fn register_builtins(console: &Console) {
+    console.register("help", "", "List the commands", |c, _| {
+        for (name, command) in c.console.commands.lock().unwrap().iter() {
+            log::info!("{name} {} - {}", command.usage, command.description);
+        }
+        Ok(())
+    });
+    console.register("clear", "", "Clear the console", |_, _| {
+        utils::clear_recent_log();
+        Ok(())
+    });
+    console.register("tp", "<x> <y> <z>", "Move the camera, keeping its direction", |c, args| {
+        let position = parse_args::<3>(args)?;
+        let values = c.camera.values.lock().unwrap().clone();
+        let direction = values.lookat - values.position;
+        c.camera.look_at(position, [position[0] + direction.x, position[1] + direction.y, position[2] + direction.z]);
+        log::info!("Moved to {position:?}");
+        Ok(())
+    });
+    console.register("set", "<setting> [value]", "Show or change a setting", |c, args| {
+        let settings = c.settings.lock().unwrap().clone();
+        match args {
+            [key] => {
+                let value = settings.field(key).ok_or_else(|| format!("Unknown setting {key}"))?;
+                log::info!("{key} = {value}");
+            }
+            [key, value @ ..] if !value.is_empty() => {
+                let changed = settings.with_field(key, &value.join(" "))?;
+                log::info!("{key} = {}", changed.field(key).unwrap());
+                c.apply_settings(changed);
+                c.console.changed_settings.lock().unwrap().insert(key.to_string());
+            }
+            _ => return Err("Expected a setting and optionally a value".into())
+        }
+        Ok(())
+    });
+    console.register("seed", "[seed]", "Show the world seed or generate the world again from a new one", |c, args| {
+        match args {
+            [] => log::info!("Seed: {}", c.settings.lock().unwrap().seed),
+            [seed] => {
+                let seed = seed.parse().map_err(|_| format!("Not a seed: {seed}"))?;
+                c.settings.lock().unwrap().seed = seed;
+                c.console.changed_settings.lock().unwrap().insert("seed".into());
+                let world = world::load_world(&c.settings.lock().unwrap(), &mut c.chunks.materials.lock().unwrap());
+                c.chunks.clear();
+                c.chunks.load_world(world);
+            }
+            _ => return Err("Expected at most one argument".into())
+        }
+        Ok(())
+    });
+    console.register("save", "", "Save the settings changed with set or seed", |c, _| {
+        // Merged into the file so overrides from the command line are not persisted
+        let changed = c.console.changed_settings.lock().unwrap();
+        let settings = Settings::read().with_fields_from(&c.settings.lock().unwrap(), changed.iter().map(String::as_str));
+        settings.save().map_err(|e| format!("Failed to save settings: {e}"))?;
+        log::info!("Settings saved to {:?}", Settings::path());
+        Ok(())
+    });
+    console.register("reload", "shaders|settings", "Reload the shaders or the settings file", |c, args| {
+        match args {
+            ["shaders"] => c.reload_shaders(),
+            ["settings"] => c.reload_settings(),
+            _ => return Err("Expected shaders or settings".into())
+        }
+        Ok(())
+    });
+}
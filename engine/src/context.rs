@@ -1,54 +1,151 @@
-use std::sync::{Arc, Mutex};
+use std::{path::Path, sync::{Arc, Mutex, PoisonError, atomic::{AtomicBool, Ordering}}};
 
 use wgpu::{Device, Queue, Surface, SurfaceConfiguration};
-use winit::{window::Window, event_loop::EventLoop, dpi::PhysicalSize};
+use winit::{window::{Fullscreen, Window}, event_loop::EventLoop, dpi::PhysicalSize};
 
-use crate::{window, Settings, Cursor, utils, Camera, shader, Chunks};
+use crate::{window, world, EngineError, Settings, Cursor, FileWatcher, Layouts, utils, Camera, shader, Chunks, RenderTarget, RenderBackend, Debug, Profiler, Ui, Console, RENDER_TARGET_FORMAT, MAX_RENDER_SCALE, MIN_RENDER_SCALE};
 
 #[derive(Clone)]
 pub struct Context {
     pub window: Arc<Window>,
-    pub settings: Arc<Settings>,
+    pub settings: Arc<Mutex<Settings>>,
     pub surface: Arc<Surface>,
     pub surface_config: Arc<Mutex<SurfaceConfiguration>>,
+    pub adapter: Arc<wgpu::Adapter>,
     pub device: Arc<Device>,
+    // Set when the device was lost or ran out of memory, for the engine to recreate it
+    pub device_lost: Arc<AtomicBool>,
     pub queue: Arc<Queue>,
     pub cursor: Arc<Cursor>,
-    pub shader: Arc<wgpu::RenderPipeline>,
+    pub layouts: Arc<Layouts>,
+    pub shader: Arc<Mutex<shader::Raytracer>>,
+    pub upscale: Arc<wgpu::RenderPipeline>,
+    pub ui: Arc<Ui>,
+    pub profiler: Arc<Profiler>,
+    pub console: Arc<Console>,
+    pub render_target: Arc<RenderTarget>,
+    pub debug: Arc<Debug>,
     pub camera: Arc<Camera>,
-    pub chunks: Arc<Chunks>
+    pub chunks: Arc<Chunks>,
+    pub settings_watcher: Arc<Mutex<FileWatcher>>,
+    pub shader_watcher: Option<Arc<Mutex<FileWatcher>>>
 }
 impl Context {
-    pub fn new(event_loop: &EventLoop<()>) -> Self {
-        let settings = Settings::read();
+    pub fn new(event_loop: &EventLoop<()>, settings: Settings) -> Result<Self, EngineError> {
+        let window = Arc::new(window::new(&settings, event_loop)?);
+        let cursor = Arc::new(Cursor::new(&window));
+        let context = Self::create(window, cursor, settings)?;
 
-        let window = window::new(&settings, event_loop);
+        let world = world::load_world(&context.settings.lock().unwrap(), &mut context.chunks.materials.lock().unwrap());
+        let (position, target) = world::overview(&world);
+        context.camera.look_at(position, target);
+        context.chunks.load_world(world);
+        // Shows the warning about the crash logged on startup
+        if utils::previous_crash_report().is_some() {
+            context.console.toggle();
+        }
+        // Pick up edits made since the last build straight away
+        if context.shader_watcher.is_some() {
+            context.reload_shaders();
+        }
+        Ok(context)
+    }
+    fn create(window: Arc<Window>, cursor: Arc<Cursor>, settings: Settings) -> Result<Self, EngineError> {
+        let instance = wgpu::Instance::new(utils::backends(&settings));
+        let surface = unsafe { instance.create_surface(&*window) };
+        let adapter = utils::create_adapter(&instance, Some(&surface), &settings)?;
+        let (device, queue) = utils::create_device_queue(&adapter)?;
+
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let lost = device_lost.clone();
+        device.on_uncaptured_error(move |error| {
+            if matches!(error, wgpu::Error::OutOfMemory { .. }) || utils::is_device_loss(&error.to_string()) {
+                lost.store(true, Ordering::Relaxed);
+            }
+            log::error!("{error}");
+        });
 
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
-        let surface = unsafe { instance.create_surface(&window) };
-        let adapter = utils::create_adapter(&instance, &surface);
-        let (device, queue) = utils::create_device_queue(&adapter);
-        
-        let surface_config = utils::configure_surface(&settings, &window, &device, &adapter, &surface);
-        
-        let cursor = Cursor::new(&window);
+        let surface_config = utils::configure_surface(&settings, &window, &device, &adapter, &surface)?;
 
-        let shader = shader::new(&device, surface_config.format);
-        let camera = Camera::new(&device, &window, &shader);
-        let chunks = Chunks::new(&device, &shader);
+        let mut backend = settings.render_backend;
+        if backend == RenderBackend::Compute && !adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS) {
+            log::warn!("Compute shaders are not supported by this adapter, falling back to the fragment backend");
+            backend = RenderBackend::Fragment;
+        }
+        log::info!("Render backend: {backend:?}");
+        let layouts = Layouts::new(&device);
+        let shader = shader::new(&device, &layouts, backend, RENDER_TARGET_FORMAT, settings.gbuffer);
+        let linear_output = utils::is_linear_output(surface_config.format);
+        let upscale = shader::upscale::new(&device, &layouts, surface_config.format);
+        let ui = Ui::new(&device, &queue, &layouts, surface_config.format, linear_output);
+        let profiler = Profiler::new(&device, &queue);
+        let render_target = RenderTarget::new(&device, &layouts, backend, &settings, window.inner_size(), linear_output);
+        let debug = Debug::new(&device, &settings);
+        let camera = Camera::new(&device, &layouts, *render_target.size.lock().unwrap(), settings.fov, &debug);
+        let chunks = Chunks::new(&device, &layouts, &settings);
+        let shader_watcher = settings.shader_hot_reload.then(|| {
+            log::info!("Watching shaders in {}", shader::SOURCE_DIR);
+            Arc::new(Mutex::new(FileWatcher::new(shader::source_paths(Path::new(shader::SOURCE_DIR), backend))))
+        });
+
+        let settings = Arc::new(Mutex::new(settings));
+        utils::set_crash_context(adapter.get_info(), settings.clone());
 
-        Self {
-            window: Arc::new(window),
-            settings: Arc::new(settings),
+        Ok(Self {
+            window,
+            settings,
             surface: Arc::new(surface),
             surface_config: Arc::new(Mutex::new(surface_config)),
+            adapter: Arc::new(adapter),
             device: Arc::new(device),
+            device_lost,
             queue: Arc::new(queue),
-            cursor: Arc::new(cursor),
-            shader: Arc::new(shader),
+            cursor,
+            layouts: Arc::new(layouts),
+            shader: Arc::new(Mutex::new(shader)),
+            upscale: Arc::new(upscale),
+            ui: Arc::new(ui),
+            profiler: Arc::new(profiler),
+            console: Arc::new(Console::new()),
+            render_target: Arc::new(render_target),
+            debug: Arc::new(debug),
             camera: Arc::new(camera),
-            chunks: Arc::new(chunks)
+            chunks: Arc::new(chunks),
+            settings_watcher: Arc::new(Mutex::new(FileWatcher::new([Settings::path()]))),
+            shader_watcher
+        })
+    }
+    /// Builds every GPU resource again on a new device after the current one was lost, carrying over the
+    /// settings, console, camera, world and debug view. The old surface and device are dropped first, as
+    /// the window can only present to one surface at a time.
+    pub fn recreate(self) -> Result<Self, EngineError> {
+        // Locks held when wgpu panicked are poisoned, but the values they guard are intact
+        fn get<T: Clone>(value: &Mutex<T>) -> T {
+            value.lock().unwrap_or_else(PoisonError::into_inner).clone()
         }
+        self.profiler.stop();
+        let settings = get(&self.settings);
+        let camera = get(&self.camera.values);
+        let materials = get(&self.chunks.materials);
+        let chunks = get(&self.chunks.chunks);
+        let octree = get(&self.chunks.octree);
+        let view = get(&self.debug.view);
+        let hud = self.ui.hud.load(Ordering::Relaxed);
+        let (window, cursor, console) = (self.window.clone(), self.cursor.clone(), self.console.clone());
+        drop(self);
+
+        let mut context = Self::create(window, cursor, settings)?;
+        // Keeps the commands registered by the game
+        context.console = console;
+        *context.camera.values.lock().unwrap() = camera;
+        context.camera.resize(*context.render_target.size.lock().unwrap());
+        *context.chunks.materials.lock().unwrap() = materials;
+        context.chunks.extend(chunks);
+        context.chunks.set_octree(octree);
+        context.debug.set_view(&context.queue, view);
+        context.ui.hud.store(hud, Ordering::Relaxed);
+        log::info!("Graphics device recreated");
+        Ok(context)
     }
     pub fn resize(&self, new_size: PhysicalSize<u32>) {
         if new_size.width == 0 || new_size.height == 0 { return }
@@ -56,12 +153,147 @@ impl Context {
         surface_config.width = new_size.width;
         surface_config.height = new_size.height;
         self.surface.configure(&self.device, &surface_config);
-        self.camera.resize(new_size);
+        let target_size = self.render_target.resize(&self.device, &self.layouts, new_size);
+        self.camera.resize(target_size);
     }
     pub fn draw(&self) {
-        self.camera.update(&self.queue);
-        self.chunks.update();
+        self.profiler.begin_frame(&self.device);
+        self.profiler.time("cpu watchers", || {
+            if self.settings_watcher.lock().unwrap().poll() {
+                self.reload_settings();
+            }
+            if self.shader_watcher.as_ref().is_some_and(|watcher| watcher.lock().unwrap().poll()) {
+                self.reload_shaders();
+            }
+        });
+        self.profiler.time("cpu update", || {
+            let target_size = self.render_target.update(&self.device, &self.layouts, &self.settings.lock().unwrap(), self.window.inner_size());
+            if let Some(target_size) = target_size {
+                self.camera.resize(target_size);
+            }
+            self.camera.update(&self.queue);
+        });
+        self.profiler.time("cpu chunk upload", || self.chunks.update(&self.device, &self.queue, &self.layouts));
+        self.draw_hud();
+        self.console.draw(&self.ui, self.window.inner_size());
         shader::draw(self);
+        self.profiler.end_frame();
+    }
+    // Frame rate and camera position in the top right corner and a crosshair in the middle
+    fn draw_hud(&self) {
+        if !self.ui.hud.load(Ordering::Relaxed) { return }
+        let size = self.window.inner_size();
+        let frame_time = self.ui.frame_time();
+        let position = self.camera.values.lock().unwrap().position;
+        let text = format!("{:.0} fps {frame_time:.1} ms\n{:.1} {:.1} {:.1}", 1000. / frame_time.max(0.001), position.x, position.y, position.z);
+        let [width, height] = crate::text_size(&text, self.ui.scale);
+        let origin = [size.width as f32 - width - 8., 8.];
+        self.ui.rect([origin[0] - 4., origin[1] - 4.], [origin[0] + width + 4., origin[1] + height + 2.], [0., 0., 0., 0.5]);
+        self.ui.text(origin, &text);
+
+        let centre = [(size.width / 2) as f32, (size.height / 2) as f32];
+        for (half_width, half_height, colour) in [(9., 2., [0., 0., 0., 0.5]), (8., 1., [1., 1., 1., 0.9])] {
+            self.ui.rect([centre[0] - half_width, centre[1] - half_height], [centre[0] + half_width, centre[1] + half_height], colour);
+            self.ui.rect([centre[0] - half_height, centre[1] - half_width], [centre[0] + half_height, centre[1] + half_width], colour);
+        }
+    }
+    /// Applies the settings that can change while running; the surface format, render backend, G-buffer,
+    /// world, octree and LOD distance only take effect after a restart.
+    pub fn apply_settings(&self, new: Settings) {
+        let mut settings = self.settings.lock().unwrap();
+        if new.present_mode != settings.present_mode {
+            let mut surface_config = self.surface_config.lock().unwrap();
+            surface_config.present_mode = utils::present_mode(new.present_mode, &self.surface.get_supported_present_modes(&self.adapter));
+            self.surface.configure(&self.device, &surface_config);
+        }
+        if new.log_filter != settings.log_filter {
+            utils::set_log_filter(&new.log_filter);
+        }
+        if new.json_log != settings.json_log {
+            utils::set_json_log(new.json_log);
+        }
+        if new.fov != settings.fov {
+            self.camera.set_fov(new.fov);
+        }
+        if new.upscale_sharpness != settings.upscale_sharpness {
+            self.queue.write_buffer(&self.render_target.upscale_buffer, 0, bytemuck::cast_slice(&[new.upscale_sharpness]));
+        }
+        let rescale = new.render_scale != settings.render_scale;
+        if rescale {
+            *self.render_target.scale.lock().unwrap() = new.render_scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        }
+        if new.surface_format != settings.surface_format || new.render_backend != settings.render_backend || new.gbuffer != settings.gbuffer || new.world_path != settings.world_path
+            || new.seed != settings.seed || new.lod_distance != settings.lod_distance || new.octree_world != settings.octree_world {
+            log::info!("Some changed settings take effect after a restart");
+        }
+        *settings = new;
+        drop(settings);
+        if rescale {
+            let target_size = self.render_target.resize(&self.device, &self.layouts, self.window.inner_size());
+            self.camera.resize(target_size);
+        }
+    }
+    /// Re-reads the settings file after it changed on disk. Unlike `Settings::read` an invalid file is only
+    /// reported, so a half-typed edit leaves the running settings and the file alone. Only the fields that apply
+    /// while running are taken from the file, so overrides from the command line stay in effect.
+    pub fn reload_settings(&self) {
+        let path = Settings::path();
+        let settings = std::fs::read_to_string(&path).map_err(|e| e.to_string())
+            .and_then(|content| Settings::parse(&content).map_err(|e| e.to_string()));
+        match settings {
+            Ok(file) => {
+                log::info!("Reloaded settings from {path:?}");
+                let settings = self.settings.lock().unwrap().with_hot_reloadable(&file);
+                self.apply_settings(settings)
+            }
+            Err(e) => log::error!("Failed to reload settings {path:?}, keeping the current ones: {e}")
+        }
+    }
+    pub fn reload_shaders(&self) {
+        let mut shader = self.shader.lock().unwrap();
+        let gbuffer = self.render_target.gbuffer.lock().unwrap().is_some();
+        let backend = shader.backend();
+        match shader::reload(&self.device, &self.layouts, backend, Path::new(shader::SOURCE_DIR), RENDER_TARGET_FORMAT, gbuffer) {
+            Ok(reloaded) => {
+                *shader = reloaded;
+                log::info!("Reloaded shaders from {}", shader::SOURCE_DIR)
+            }
+            Err(e) => log::error!("Failed to reload shaders, keeping the previous pipeline:\n{e}")
+        }
+    }
+    pub fn toggle_fullscreen(&self) {
+        let fullscreen = self.window.fullscreen().is_none();
+        self.window.set_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)));
+        self.settings.lock().unwrap().window_fullscreen = fullscreen;
+    }
+    /// Stores the window state in the settings file, leaving the rest of the file as it is on disk so
+    /// overrides from the command line are not persisted.
+    pub fn save_window_state(&self) {
+        let mut settings = Settings::read();
+        settings.window_fullscreen = self.window.fullscreen().is_some();
+        settings.window_maximized = self.window.is_maximized();
+        if !settings.window_fullscreen && !settings.window_maximized {
+            let size = self.window.inner_size();
+            settings.window_size = Some([size.width, size.height]);
+            settings.window_position = self.window.outer_position().ok().map(|p| [p.x, p.y]);
+        }
+        match settings.save() {
+            Ok(()) => log::info!("Window state saved"),
+            Err(e) => log::error!("Failed to save settings: {e}")
+        }
+    }
+    pub fn save_gbuffer(&self) {
+        let gbuffer = self.render_target.gbuffer.lock().unwrap();
+        let Some(gbuffer) = gbuffer.as_ref() else { return log::warn!("G-buffer output is disabled in the settings") };
+        let dir = utils::data_dir().join("gbuffer");
+        let data = match gbuffer.read(&self.device, &self.queue) {
+            Ok(data) => data,
+            Err(e) => return log::error!("Failed to read back the G-buffer: {e}")
+        };
+        match data.save(&dir) {
+            Ok(()) => log::info!("G-buffer saved to {dir:?}"),
+            Err(e) => log::error!("Failed to save G-buffer to {dir:?}: {e}")
+        }
     }
 }
 
@@ -0,0 +1,74 @@
+use std::sync::Mutex;
+
+use wgpu::util::DeviceExt;
+
+use crate::Settings;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DebugView {
+    Shaded,
+    Steps,
+    ChunkBounds,
+    Normals,
+    Depth,
+    Material,
+    ChunkIndex
+}
+impl DebugView {
+    pub const ALL: [Self; 7] = [
+        Self::Shaded, Self::Steps, Self::ChunkBounds, Self::Normals, Self::Depth, Self::Material, Self::ChunkIndex
+    ];
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugBinding {
+    pub mode: u32,
+    pub max_steps: u32,
+    pub depth_range: f32,
+    pub _padding: u32
+}
+
+pub struct Debug {
+    pub buffer: wgpu::Buffer,
+    pub view: Mutex<DebugView>,
+    pub max_steps: u32,
+    pub depth_range: f32
+}
+impl Debug {
+    pub fn new(device: &wgpu::Device, settings: &Settings) -> Self {
+        let view = DebugView::Shaded;
+        let max_steps = 128;
+        let depth_range = settings.far;
+        let buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&[DebugBinding { mode: view as u32, max_steps, depth_range, _padding: 0 }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+            }
+        );
+        Self {
+            buffer,
+            view: Mutex::new(view),
+            max_steps,
+            depth_range
+        }
+    }
+    pub fn set_view(&self, queue: &wgpu::Queue, view: DebugView) {
+        *self.view.lock().unwrap() = view;
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[DebugBinding {
+            mode: view as u32,
+            max_steps: self.max_steps,
+            depth_range: self.depth_range,
+            _padding: 0
+        }]));
+        log::info!("Debug view: {view:?}");
+    }
+    pub fn cycle(&self, queue: &wgpu::Queue) {
+        let next = self.view.lock().unwrap().next();
+        self.set_view(queue, next)
+    }
+}
@@ -1,38 +1,69 @@
 use winit::{event_loop::{EventLoop, ControlFlow}, platform::run_return::EventLoopExtRunReturn,
     event::{Event, WindowEvent, KeyboardInput, VirtualKeyCode, ElementState}};
 
-use crate::Context;
+use std::{sync::atomic::Ordering, time::{Duration, Instant}};
+
+use crate::{utils, Context, EngineError, Settings};
+
+// Losing the device again this soon after recreating it ends the engine instead of recreating it forever
+const MIN_RECOVERY_INTERVAL: Duration = Duration::from_secs(10);
 
 pub struct Engine {
     pub event_loop: Option<EventLoop<()>>,
     pub context: Context
 }
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new().expect("Failed to start the engine")
+    }
+}
 impl Engine {
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self, EngineError> {
         crate::start_logger();
-        
+        let settings = Settings::read();
+        crate::configure_logger(&settings);
+        Self::with_settings(settings)
+    }
+    // Expects the logger to be running already
+    pub fn with_settings(settings: Settings) -> Result<Self, EngineError> {
         let event_loop = EventLoop::new();
-        let context = Context::new(&event_loop);
+        let context = Context::new(&event_loop, settings)?;
 
-        Self {
+        Ok(Self {
             event_loop: Some(event_loop),
             context
-        }
+        })
     }
-    pub fn start(mut self) {
+    /// Runs until the window is closed, or fails when the graphics device is lost and can't be recreated.
+    pub fn start(mut self) -> Result<(), EngineError> {
         log::trace!("Start");
-        let c = self.context;
+        let mut context = Some(self.context);
+        let mut recovered_at: Option<Instant> = None;
+        let mut result = Ok(());
         self.event_loop.take().unwrap().run_return(|event, _, control_flow| {
+            let Some(c) = &context else { return };
             match event {
                 Event::WindowEvent { event, .. } => match event {
                     WindowEvent::KeyboardInput { input: KeyboardInput { virtual_keycode: Some(key), state, .. }, .. } => {
+                        let console = c.console.is_open();
                         match (key, state) {
+                            (VirtualKeyCode::Grave, ElementState::Pressed) => c.console.toggle(),
+                            (VirtualKeyCode::Escape, ElementState::Pressed) if console => c.console.toggle(),
+                            (VirtualKeyCode::Up, ElementState::Pressed) if console => c.console.browse_history(true),
+                            (VirtualKeyCode::Down, ElementState::Pressed) if console => c.console.browse_history(false),
                             (VirtualKeyCode::Escape, ElementState::Pressed) =>
                                 *control_flow = ControlFlow::Exit,
+                            (VirtualKeyCode::F1, ElementState::Pressed) if !console => { c.ui.hud.fetch_xor(true, Ordering::Relaxed); }
+                            (VirtualKeyCode::F2, ElementState::Pressed) if !console => c.save_gbuffer(),
+                            (VirtualKeyCode::F3, ElementState::Pressed) if !console => c.debug.cycle(&c.queue),
+                            (VirtualKeyCode::F4, ElementState::Pressed) if !console => c.profiler.toggle(),
+                            (VirtualKeyCode::F11, ElementState::Pressed) if !console => c.toggle_fullscreen(),
                             _ => {}
                         }
                     },
 
+                    WindowEvent::ReceivedCharacter(character) if c.console.is_open() => c.console.input_char(c, character),
+
                     WindowEvent::CursorLeft {..} => c.cursor.left(),
                     WindowEvent::CursorEntered {..} => c.cursor.entered(),
                     WindowEvent::Focused(focus) => if focus { c.cursor.entered() } else { c.cursor.left() },
@@ -47,9 +78,32 @@ impl Engine {
                     _ => {}
                 },
                 Event::MainEventsCleared => c.window.request_redraw(),
-                Event::RedrawRequested(_) => c.draw(),
+                Event::RedrawRequested(_) => if let Err(e) = utils::catch_device_loss(|| c.draw()) {
+                    log::error!("{e}");
+                    c.device_lost.store(true, Ordering::Relaxed);
+                },
                 _ => {}
             }
+            if c.device_lost.load(Ordering::Relaxed) {
+                if recovered_at.is_some_and(|at| at.elapsed() < MIN_RECOVERY_INTERVAL) {
+                    result = Err(EngineError::DeviceLost);
+                    return *control_flow = ControlFlow::Exit
+                }
+                log::warn!("Graphics device lost, recreating it");
+                match context.take().unwrap().recreate() {
+                    Ok(recreated) => context = Some(recreated),
+                    Err(e) => {
+                        result = Err(e);
+                        *control_flow = ControlFlow::Exit
+                    }
+                }
+                recovered_at = Some(Instant::now());
+            }
         });
+        if let Some(c) = context {
+            c.profiler.stop();
+            c.save_window_state();
+        }
+        result
     }
-}
\ No newline at end of file
+}
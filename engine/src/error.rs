@@ -0,0 +1,39 @@
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum EngineError {
+    CreateWindow(winit::error::OsError),
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    UnsupportedSurface,
+    // Lost again soon after being recreated, so recreating it once more is unlikely to help
+    DeviceLost,
+    Io(io::Error)
+}
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::CreateWindow(e) => write!(f, "Failed to create the window: {e}"),
+            Self::NoAdapter => write!(f, "No compatible graphics adapter found"),
+            Self::RequestDevice(e) => write!(f, "Failed to create the graphics device: {e}"),
+            Self::UnsupportedSurface => write!(f, "The graphics adapter cannot present to the window"),
+            Self::DeviceLost => write!(f, "The graphics device keeps getting lost or running out of memory"),
+            Self::Io(e) => write!(f, "{e}")
+        }
+    }
+}
+impl std::error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CreateWindow(e) => Some(e),
+            Self::RequestDevice(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::NoAdapter | Self::UnsupportedSurface | Self::DeviceLost => None
+        }
+    }
+}
+impl From<io::Error> for EngineError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
@@ -0,0 +1,306 @@
+use std::{collections::HashMap, fs::File, io::{self, BufWriter, Write}, path::Path};
+
+use crate::{Chunk, Materials, CHUNK_SIZE};
+
+const VOX_MAX_SIZE: i32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub min: [i32;3],
+    pub max: [i32;3]
+}
+impl Region {
+    pub fn new(a: [i32;3], b: [i32;3]) -> Self {
+        Self {
+            min: [0, 1, 2].map(|i| a[i].min(b[i])),
+            max: [0, 1, 2].map(|i| a[i].max(b[i]))
+        }
+    }
+    pub fn of_chunks(chunks: &[Chunk]) -> Option<Self> {
+        chunks.iter().map(|chunk| {
+            let min = [0, 1, 2].map(|i| chunk.position[i].floor() as i32);
+            Self { min, max: min.map(|v| v + CHUNK_SIZE as i32) }
+        }).reduce(|a, b| Self::new(
+            [0, 1, 2].map(|i| a.min[i].min(b.min[i])),
+            [0, 1, 2].map(|i| a.max[i].max(b.max[i]))
+        ))
+    }
+    /// The part of this region inside `other`, empty when they don't overlap.
+    pub fn intersection(&self, other: &Region) -> Self {
+        let min = [0, 1, 2].map(|i| self.min[i].max(other.min[i]));
+        Self { min, max: [0, 1, 2].map(|i| self.max[i].min(other.max[i]).max(min[i])) }
+    }
+    pub fn size(&self) -> [usize;3] {
+        [0, 1, 2].map(|i| (self.max[i] - self.min[i]) as usize)
+    }
+}
+
+pub struct VoxelGrid {
+    pub region: Region,
+    pub data: Vec<u32>
+}
+impl VoxelGrid {
+    pub fn from_chunks(chunks: &[Chunk], region: Region) -> Self {
+        // Everything outside the chunks is empty, so a box given on the command line can't allocate past them
+        let bounds = Region::of_chunks(chunks).unwrap_or(Region { min: region.min, max: region.min });
+        let region = region.intersection(&bounds);
+        let [sx, sy, sz] = region.size();
+        let mut data = vec![0; sx * sy * sz];
+        for chunk in chunks {
+            let origin = [0, 1, 2].map(|i| chunk.position[i].floor() as i32);
+            for (x, plane) in chunk.data.iter().enumerate() {
+                for (y, row) in plane.iter().enumerate() {
+                    for (z, material) in row.iter().enumerate() {
+                        let world = [origin[0] + x as i32, origin[1] + y as i32, origin[2] + z as i32];
+                        if (0..3).any(|i| world[i] < region.min[i] || world[i] >= region.max[i]) { continue }
+                        let [lx, ly, lz] = [0, 1, 2].map(|i| (world[i] - region.min[i]) as usize);
+                        data[(lx * sy + ly) * sz + lz] = *material;
+                    }
+                }
+            }
+        }
+        Self { region, data }
+    }
+    pub fn get(&self, local: [i32;3]) -> u32 {
+        let [sx, sy, sz] = self.region.size();
+        if local.iter().zip([sx, sy, sz]).any(|(v, s)| *v < 0 || *v as usize >= s) { return 0 }
+        self.data[(local[0] as usize * sy + local[1] as usize) * sz + local[2] as usize]
+    }
+}
+
+pub struct Quad {
+    pub corners: [[i32;3];4],
+    pub normal: [i32;3],
+    pub material: u32
+}
+
+// Merges coplanar visible faces of equal material into rectangles, one slice at a time per axis and direction
+pub fn greedy_mesh(grid: &VoxelGrid) -> Vec<Quad> {
+    let size = grid.region.size().map(|v| v as i32);
+    let mut quads = vec![];
+    for d in 0..3 {
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+        for sign in [-1, 1] {
+            let mut mask = vec![0u32; (size[u] * size[v]) as usize];
+            for slice in 0..size[d] {
+                for j in 0..size[v] {
+                    for i in 0..size[u] {
+                        let mut p = [0;3];
+                        p[d] = slice;
+                        p[u] = i;
+                        p[v] = j;
+                        let material = grid.get(p);
+                        p[d] += sign;
+                        mask[(j * size[u] + i) as usize] = if material != 0 && grid.get(p) == 0 { material } else { 0 };
+                    }
+                }
+                for j in 0..size[v] {
+                    let mut i = 0;
+                    while i < size[u] {
+                        let material = mask[(j * size[u] + i) as usize];
+                        if material == 0 { i += 1; continue }
+                        let mut w = 1;
+                        while i + w < size[u] && mask[(j * size[u] + i + w) as usize] == material { w += 1 }
+                        let mut h = 1;
+                        'grow: while j + h < size[v] {
+                            for k in 0..w {
+                                if mask[((j + h) * size[u] + i + k) as usize] != material { break 'grow }
+                            }
+                            h += 1;
+                        }
+                        for y in 0..h {
+                            for x in 0..w {
+                                mask[((j + y) * size[u] + i + x) as usize] = 0;
+                            }
+                        }
+
+                        let mut base = grid.region.min;
+                        base[d] += slice + if sign > 0 { 1 } else { 0 };
+                        base[u] += i;
+                        base[v] += j;
+                        let (mut du, mut dv) = ([0;3], [0;3]);
+                        du[u] = w;
+                        dv[v] = h;
+                        let add = |a: [i32;3], b: [i32;3]| [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+                        let corners = if sign > 0 {
+                            [base, add(base, du), add(add(base, du), dv), add(base, dv)]
+                        } else {
+                            [base, add(base, dv), add(add(base, du), dv), add(base, du)]
+                        };
+                        let mut normal = [0;3];
+                        normal[d] = sign;
+                        quads.push(Quad { corners, normal, material });
+                        i += w;
+                    }
+                }
+            }
+        }
+    }
+    quads.sort_by_key(|q| q.material);
+    quads
+}
+
+pub fn export_region(path: &Path, chunks: &[Chunk], materials: &Materials, region: Region) -> io::Result<()> {
+    let grid = VoxelGrid::from_chunks(chunks, region);
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+    match extension.as_str() {
+        "vox" => write_vox(&mut BufWriter::new(File::create(path)?), &grid, materials),
+        "obj" => write_obj(path, &greedy_mesh(&grid), materials),
+        "ply" => write_ply(&mut BufWriter::new(File::create(path)?), &greedy_mesh(&grid), materials),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unsupported export format: {path:?}")))
+    }
+}
+
+fn color(materials: &Materials, material: u32) -> [u8;4] {
+    materials.get(material).unwrap_or_else(|| {
+        let h = material.wrapping_mul(2654435761);
+        [h as u8, (h >> 8) as u8, (h >> 16) as u8, 255]
+    })
+}
+
+// Regions larger than MagicaVoxel's 256^3 model limit are split into several models placed by the scene graph
+pub fn write_vox(w: &mut impl Write, grid: &VoxelGrid, materials: &Materials) -> io::Result<()> {
+    let mut palette = vec![];
+    let mut palette_index = HashMap::new();
+    for material in grid.data.iter().copied().filter(|m| *m != 0) {
+        if palette_index.contains_key(&material) { continue }
+        let c = color(materials, material);
+        let index = if palette.len() < 255 {
+            palette.push(c);
+            palette.len() as u8
+        } else {
+            let nearest = palette.iter().enumerate().min_by_key(|(_, p)| (0..3).map(|i| (p[i] as i32 - c[i] as i32).pow(2)).sum::<i32>()).unwrap().0;
+            log::warn!("More than 255 materials in VOX export, material {material} mapped to palette index {}", nearest + 1);
+            nearest as u8 + 1
+        };
+        palette_index.insert(material, index);
+    }
+
+    let size = grid.region.size().map(|v| v as i32);
+    let mut models = vec![];
+    let mut tiles = vec![];
+    for tx in (0..size[0]).step_by(VOX_MAX_SIZE as usize) {
+        for ty in (0..size[1]).step_by(VOX_MAX_SIZE as usize) {
+            for tz in (0..size[2]).step_by(VOX_MAX_SIZE as usize) {
+                let tile = [tx, ty, tz];
+                let tile_size = [0, 1, 2].map(|i| (size[i] - tile[i]).min(VOX_MAX_SIZE));
+                let mut xyzi = vec![];
+                for x in 0..tile_size[0] {
+                    for y in 0..tile_size[1] {
+                        for z in 0..tile_size[2] {
+                            let material = grid.get([tx + x, ty + y, tz + z]);
+                            if material != 0 { xyzi.push([x as u8, y as u8, z as u8, palette_index[&material]]) }
+                        }
+                    }
+                }
+                if xyzi.is_empty() { continue }
+                let mut content = vec![];
+                content.extend(bytes_u32(&[tile_size[0] as u32, tile_size[1] as u32, tile_size[2] as u32]));
+                models.extend(vox_chunk(b"SIZE", &content, &[]));
+                content.clear();
+                content.extend((xyzi.len() as u32).to_le_bytes());
+                content.extend(xyzi.iter().flatten());
+                models.extend(vox_chunk(b"XYZI", &content, &[]));
+                tiles.push((tile, tile_size));
+            }
+        }
+    }
+
+    // Scene graph: root transform 0 -> group 1 -> (transform, shape) pair per model
+    let mut graph = vec![];
+    let mut content = bytes_u32(&[0, 0, 1, u32::MAX, 0, 1, 0]);
+    graph.extend(vox_chunk(b"nTRN", &content, &[]));
+    content = bytes_u32(&[1, 0, tiles.len() as u32]);
+    content.extend(bytes_u32(&(0..tiles.len() as u32).map(|i| 2 + i * 2).collect::<Vec<_>>()));
+    graph.extend(vox_chunk(b"nGRP", &content, &[]));
+    for (model, (tile, tile_size)) in tiles.iter().enumerate() {
+        let id = 2 + model as u32 * 2;
+        let t = [0, 1, 2].map(|i| grid.region.min[i] + tile[i] + tile_size[i] / 2);
+        content = bytes_u32(&[id, 0, id + 1, u32::MAX, 0, 1, 1]);
+        content.extend(vox_string("_t"));
+        content.extend(vox_string(&format!("{} {} {}", t[0], t[1], t[2])));
+        graph.extend(vox_chunk(b"nTRN", &content, &[]));
+        content = bytes_u32(&[id + 1, 0, 1, model as u32, 0]);
+        graph.extend(vox_chunk(b"nSHP", &content, &[]));
+    }
+
+    let mut rgba = vec![0u8; 256 * 4];
+    for (i, c) in palette.iter().enumerate() {
+        rgba[i * 4..i * 4 + 4].copy_from_slice(c);
+    }
+    let mut children = models;
+    children.extend(graph);
+    children.extend(vox_chunk(b"RGBA", &rgba, &[]));
+
+    w.write_all(b"VOX ")?;
+    w.write_all(&150u32.to_le_bytes())?;
+    w.write_all(&vox_chunk(b"MAIN", &[], &children))?;
+    w.flush()
+}
+
+fn bytes_u32(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn vox_string(s: &str) -> Vec<u8> {
+    let mut bytes = (s.len() as u32).to_le_bytes().to_vec();
+    bytes.extend(s.as_bytes());
+    bytes
+}
+
+fn vox_chunk(id: &[u8;4], content: &[u8], children: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend(bytes_u32(&[content.len() as u32, children.len() as u32]));
+    bytes.extend(content);
+    bytes.extend(children);
+    bytes
+}
+
+/// Writes a Wavefront OBJ with a companion `.mtl` file next to it holding one material per voxel material.
+pub fn write_obj(path: &Path, quads: &[Quad], materials: &Materials) -> io::Result<()> {
+    let mtl_path = path.with_extension("mtl");
+    let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "# d32 export")?;
+    writeln!(w, "mtllib {}", mtl_path.file_name().unwrap().to_string_lossy())?;
+    for quad in quads {
+        for [x, y, z] in quad.corners {
+            writeln!(w, "v {x} {y} {z}")?;
+        }
+    }
+    let normals = [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]];
+    for [x, y, z] in normals {
+        writeln!(w, "vn {x} {y} {z}")?;
+    }
+    let mut current = None;
+    for (i, quad) in quads.iter().enumerate() {
+        if current != Some(quad.material) {
+            current = Some(quad.material);
+            let [r, g, b, a] = color(materials, quad.material).map(|c| c as f32 / 255.);
+            writeln!(mtl, "newmtl material_{}\nKd {r} {g} {b}\nd {a}\n", quad.material)?;
+            writeln!(w, "usemtl material_{}", quad.material)?;
+        }
+        let n = normals.iter().position(|n| *n == quad.normal).unwrap() + 1;
+        let v = i * 4 + 1;
+        writeln!(w, "f {}//{n} {}//{n} {}//{n} {}//{n}", v, v + 1, v + 2, v + 3)?;
+    }
+    mtl.flush()?;
+    w.flush()
+}
+
+pub fn write_ply(w: &mut impl Write, quads: &[Quad], materials: &Materials) -> io::Result<()> {
+    writeln!(w, "ply\nformat ascii 1.0\ncomment d32 export")?;
+    writeln!(w, "element vertex {}\nproperty float x\nproperty float y\nproperty float z", quads.len() * 4)?;
+    writeln!(w, "element face {}\nproperty list uchar int vertex_indices", quads.len())?;
+    writeln!(w, "property uchar red\nproperty uchar green\nproperty uchar blue\nend_header")?;
+    for quad in quads {
+        for [x, y, z] in quad.corners {
+            writeln!(w, "{x} {y} {z}")?;
+        }
+    }
+    for (i, quad) in quads.iter().enumerate() {
+        let [r, g, b, _] = color(materials, quad.material);
+        writeln!(w, "4 {} {} {} {} {r} {g} {b}", i * 4, i * 4 + 1, i * 4 + 2, i * 4 + 3)?;
+    }
+    w.flush()
+}
@@ -0,0 +1,149 @@
+use std::{io, path::Path, sync::mpsc};
+
+use winit::dpi::PhysicalSize;
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const MATERIAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+pub struct GBufferTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub bytes_per_pixel: u32
+}
+impl GBufferTexture {
+    pub fn new(device: &wgpu::Device, size: PhysicalSize<u32>, format: wgpu::TextureFormat, bytes_per_pixel: u32, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: size.width, height: size.height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view, bytes_per_pixel }
+    }
+    /// Copies the texture back to the CPU, failing when the buffer cannot be mapped, as after a device loss.
+    pub fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue, size: PhysicalSize<u32>) -> io::Result<Vec<u8>> {
+        let row_bytes = size.width * self.bytes_per_pixel;
+        let padded_row_bytes = row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("G-buffer readback"),
+            size: (padded_row_bytes * size.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: None
+                }
+            },
+            wgpu::Extent3d { width: size.width, height: size.height, depth_or_array_layers: 1 }
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| { let _ = sender.send(result); });
+        device.poll(wgpu::Maintain::Wait);
+        match receiver.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(io::Error::other(format!("Failed to map the readback buffer: {e}"))),
+            Err(_) => return Err(io::Error::other("The readback buffer was never mapped"))
+        }
+        let mapped = slice.get_mapped_range();
+        let data = unpad_rows(&mapped, row_bytes as usize, padded_row_bytes as usize);
+        drop(mapped);
+        buffer.unmap();
+        Ok(data)
+    }
+}
+
+pub struct GBuffer {
+    pub size: PhysicalSize<u32>,
+    pub depth: GBufferTexture,
+    pub normal: GBufferTexture,
+    pub material: GBufferTexture
+}
+impl GBuffer {
+    pub fn new(device: &wgpu::Device, size: PhysicalSize<u32>) -> Self {
+        Self {
+            size,
+            depth: GBufferTexture::new(device, size, DEPTH_FORMAT, 4, "G-buffer depth"),
+            normal: GBufferTexture::new(device, size, NORMAL_FORMAT, 8, "G-buffer normal"),
+            material: GBufferTexture::new(device, size, MATERIAL_FORMAT, 4, "G-buffer material")
+        }
+    }
+    pub fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> io::Result<GBufferData> {
+        let depth = self.depth.read(device, queue, self.size)?;
+        let normal = self.normal.read(device, queue, self.size)?;
+        let material = self.material.read(device, queue, self.size)?;
+        Ok(GBufferData::from_bytes(self.size, &depth, &normal, &material))
+    }
+}
+
+/// Drops the padding wgpu requires at the end of each row of a texture copy.
+pub fn unpad_rows(padded: &[u8], row_bytes: usize, padded_row_bytes: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(padded.len() / padded_row_bytes * row_bytes);
+    for row in padded.chunks(padded_row_bytes) {
+        data.extend_from_slice(&row[..row_bytes]);
+    }
+    data
+}
+
+pub struct GBufferData {
+    pub size: PhysicalSize<u32>,
+    pub depth: Vec<f32>,
+    pub normal: Vec<[f32;4]>,
+    pub material: Vec<u32>
+}
+impl GBufferData {
+    /// Converts tightly packed rows of the depth, normal and material formats.
+    pub fn from_bytes(size: PhysicalSize<u32>, depth: &[u8], normal: &[u8], material: &[u8]) -> Self {
+        Self {
+            size,
+            depth: bytemuck::pod_collect_to_vec(depth),
+            normal: bytemuck::pod_collect_to_vec::<u8, u16>(normal).chunks(4)
+                .map(|n| [f16_to_f32(n[0]), f16_to_f32(n[1]), f16_to_f32(n[2]), f16_to_f32(n[3])])
+                .collect(),
+            material: bytemuck::pod_collect_to_vec(material)
+        }
+    }
+    pub fn save(&self, dir: &Path) -> image::ImageResult<()> {
+        std::fs::create_dir_all(dir)?;
+        let (width, height) = (self.size.width, self.size.height);
+        let max_depth = self.depth.iter().copied().filter(|d| *d < 1e29).fold(0f32, f32::max).max(f32::EPSILON);
+        image::GrayImage::from_fn(width, height, |x, y| {
+            let d = self.depth[(y * width + x) as usize];
+            image::Luma([if d < 1e29 { 255 - (d / max_depth * 255.) as u8 } else { 0 }])
+        }).save(dir.join("depth.png"))?;
+        image::RgbImage::from_fn(width, height, |x, y| {
+            let n = self.normal[(y * width + x) as usize];
+            image::Rgb([0, 1, 2].map(|i| ((n[i] * 0.5 + 0.5) * n[3] * 255.) as u8))
+        }).save(dir.join("normal.png"))?;
+        image::RgbImage::from_fn(width, height, |x, y| {
+            let h = self.material[(y * width + x) as usize].wrapping_mul(2654435761);
+            image::Rgb([h as u8, (h >> 8) as u8, (h >> 16) as u8])
+        }).save(dir.join("material.png"))
+    }
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1. } else { 1. };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 => if mantissa == 0. { sign * f32::INFINITY } else { f32::NAN },
+        _ => sign * (1. + mantissa / 1024.) * 2f32.powi(exponent - 15)
+    }
+}
@@ -0,0 +1,41 @@
+use std::{io, path::Path, sync::{Arc, Mutex}};
+
+use winit::dpi::PhysicalSize;
+
+use crate::{load_world, overview, shader, utils, Camera, Chunks, Debug, EngineError, GBufferTexture, Layouts, RenderBackend, Settings, RENDER_TARGET_FORMAT};
+
+/// Renders a single frame of the world described by `settings` without opening a window and saves it to `path`.
+/// The camera overlooks the whole world unless a position and target are given.
+pub fn render_image(settings: &Settings, size: [u32;2], camera: Option<([f32;3], [f32;3])>, path: &Path) -> Result<(), EngineError> {
+    let size = PhysicalSize::new(size[0], size[1]);
+    let instance = wgpu::Instance::new(utils::backends(settings));
+    let adapter = utils::create_adapter(&instance, None, settings)?;
+    utils::set_crash_context(adapter.get_info(), Arc::new(Mutex::new(settings.clone())));
+    log::info!("Rendering {}x{}", size.width, size.height);
+    let (device, queue) = utils::create_device_queue(&adapter)?;
+
+    let layouts = Layouts::new(&device);
+    let shader = shader::new(&device, &layouts, RenderBackend::Fragment, RENDER_TARGET_FORMAT, false);
+    let debug = Debug::new(&device, settings);
+    let camera_binding = Camera::new(&device, &layouts, size, settings.fov, &debug);
+    let chunks = Chunks::new(&device, &layouts, settings);
+    let world = load_world(settings, &mut chunks.materials.lock().unwrap());
+    let (position, target) = camera.unwrap_or_else(|| overview(&world));
+    camera_binding.look_at(position, target);
+    chunks.load_world(world);
+    camera_binding.update(&queue);
+    chunks.update(&device, &queue, &layouts);
+
+    let output = GBufferTexture::new(&device, size, RENDER_TARGET_FORMAT, 4, "Headless output");
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    if let shader::Raytracer::Fragment(pipeline) = &shader {
+        shader::trace_fragment(&mut encoder, pipeline, &camera_binding.bind_group, &chunks.bind_group.lock().unwrap(), vec![&output.view]);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let image = image::RgbaImage::from_raw(size.width, size.height, output.read(&device, &queue, size)?).unwrap();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    image.save(path).map_err(|e| io::Error::other(e).into())
+}
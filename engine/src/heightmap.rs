@@ -0,0 +1,125 @@
+use std::{collections::HashMap, path::Path};
+
+use image::{imageops::FilterType, DynamicImage, ImageResult};
+
+use crate::{Chunk, Materials, CHUNK_SIZE};
+
+// Colour maps are merged down to at most this many surface materials
+pub const MAX_SURFACE_COLORS: usize = 256;
+
+// A band of voxels below the surface, the last layer reaches down to the bottom of the column
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaterialLayer {
+    pub depth: u32,
+    pub color: [u8;4]
+}
+impl MaterialLayer {
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self { depth: 1, color: [86, 152, 64, 255] },
+            Self { depth: 3, color: [121, 85, 58, 255] },
+            Self { depth: u32::MAX, color: [128, 128, 128, 255] }
+        ]
+    }
+}
+
+pub struct Heightmap {
+    pub size: [u32;2],
+    pub heights: Vec<u32>,
+    pub colors: Option<Vec<[u8;4]>>
+}
+impl Heightmap {
+    /// Reads a greyscale image where black is the ground level and white `vertical_scale` voxels above it.
+    pub fn read(path: impl AsRef<Path>, vertical_scale: f32) -> ImageResult<Self> {
+        Ok(Self::from_image(&image::open(path)?, vertical_scale))
+    }
+    pub fn from_image(image: &DynamicImage, vertical_scale: f32) -> Self {
+        let luma = image.to_luma16();
+        let heights = luma.pixels().map(|p| (p[0] as f32 / u16::MAX as f32 * vertical_scale).round().max(0.) as u32).collect();
+        Self { size: [luma.width(), luma.height()], heights, colors: None }
+    }
+    /// Colours the top layer from an image, which is resized to match the heightmap if needed.
+    pub fn read_colors(&mut self, path: impl AsRef<Path>) -> ImageResult<()> {
+        self.set_colors(&image::open(path)?);
+        Ok(())
+    }
+    pub fn set_colors(&mut self, image: &DynamicImage) {
+        let [width, height] = self.size;
+        let mut rgba = image.to_rgba8();
+        if rgba.dimensions() != (width, height) {
+            rgba = image::imageops::resize(&rgba, width, height, FilterType::Triangle);
+        }
+        self.colors = Some(rgba.pixels().map(|p| p.0).collect());
+    }
+    pub fn height(&self, x: u32, y: u32) -> u32 {
+        self.heights[(y * self.size[0] + x) as usize]
+    }
+    /// Extrudes every pixel into a column of voxels starting at `offset`, with image x and y along the world
+    /// x and y axes and height along z.
+    pub fn to_chunks(&self, offset: [i32;3], layers: &[MaterialLayer], materials: &mut Materials) -> Vec<Chunk> {
+        if layers.is_empty() { return vec![] }
+        let layer_materials: Vec<u32> = layers.iter().map(|layer| materials.add(layer.color)).collect();
+        let colors = self.colors.as_deref().map(palette_colors);
+        let mut chunks: HashMap<[i32;3], Chunk> = HashMap::new();
+        for y in 0..self.size[1] {
+            for x in 0..self.size[0] {
+                let top = self.height(x, y);
+                let surface = colors.as_ref().map(|colors| materials.add(colors[(y * self.size[0] + x) as usize]));
+                let mut layer = 0;
+                let mut layer_bottom = layers[0].depth;
+                for depth in 0..=top {
+                    while depth >= layer_bottom && layer + 1 < layers.len() {
+                        layer += 1;
+                        layer_bottom = layer_bottom.saturating_add(layers[layer].depth);
+                    }
+                    let material = match surface {
+                        Some(surface) if layer == 0 => surface,
+                        _ => layer_materials[layer]
+                    };
+                    let world = [offset[0] + x as i32, offset[1] + y as i32, offset[2] + (top - depth) as i32];
+                    let key = world.map(|v| v.div_euclid(CHUNK_SIZE as i32));
+                    let [cx, cy, cz] = world.map(|v| v.rem_euclid(CHUNK_SIZE as i32) as usize);
+                    chunks.entry(key).or_insert_with(|| {
+                        let mut chunk = Chunk::new();
+                        chunk.position = [key[0] as f32, key[1] as f32, key[2] as f32, 0.].map(|v| v * CHUNK_SIZE as f32);
+                        chunk
+                    }).data[cx][cy][cz] = material;
+                }
+            }
+        }
+        let mut chunks: Vec<Chunk> = chunks.into_values().collect();
+        chunks.sort_by(|a, b| a.position.partial_cmp(&b.position).unwrap());
+        chunks
+    }
+}
+
+/// Opaque copies of `colors` with at most `MAX_SURFACE_COLORS` distinct values. Low bits of each channel are
+/// dropped until few enough remain, and the colours sharing the remaining bits become their average.
+pub fn palette_colors(colors: &[[u8;4]]) -> Vec<[u8;4]> {
+    let key = |c: &[u8;4], bits: u32| [c[0], c[1], c[2]].map(|v| v >> (8 - bits));
+    let group = |bits| {
+        let mut groups: HashMap<[u8;3], ([u64;3], u64)> = HashMap::new();
+        for color in colors {
+            let (sum, count) = groups.entry(key(color, bits)).or_default();
+            for i in 0..3 { sum[i] += color[i] as u64 }
+            *count += 1;
+        }
+        groups
+    };
+    let mut bits = 8;
+    let mut groups = group(bits);
+    let distinct = groups.len();
+    // Ends by one bit per channel, which leaves at most 8 groups
+    while groups.len() > MAX_SURFACE_COLORS {
+        bits -= 1;
+        groups = group(bits);
+    }
+    if bits < 8 {
+        log::warn!("Colour map has {distinct} colours, merged into {} materials", groups.len());
+    }
+    colors.iter().map(|color| {
+        let (sum, count) = groups[&key(color, bits)];
+        let [r, g, b] = sum.map(|v| ((v + count / 2) / count) as u8);
+        [r, g, b, 255]
+    }).collect()
+}
@@ -0,0 +1,146 @@
+use crate::{RenderBackend, DEPTH_FORMAT, MATERIAL_FORMAT, NORMAL_FORMAT, RENDER_TARGET_FORMAT};
+
+const RAYTRACE_STAGES: wgpu::ShaderStages = wgpu::ShaderStages::FRAGMENT.union(wgpu::ShaderStages::COMPUTE);
+
+// Every bind group layout used by the pipelines, declared up front so bind groups do not depend on which
+// bindings a shader happens to use and can be shared between pipelines
+pub struct Layouts {
+    // Group 0 of the raytracers: camera and debug uniforms
+    pub camera: wgpu::BindGroupLayout,
+    // Group 1 of the raytracers: chunks, chunk count, octree, octree info and materials
+    pub chunks: wgpu::BindGroupLayout,
+    // Group 2 of the compute raytracer: the render target, then depth, normal and material with the G-buffer
+    pub output: wgpu::BindGroupLayout,
+    pub gbuffer_output: wgpu::BindGroupLayout,
+    // Group 0 of the upscale pass: render target, sampler and sharpness
+    pub upscale: wgpu::BindGroupLayout,
+    // Group 0 of the overlay pass: atlas and sampler
+    pub overlay: wgpu::BindGroupLayout
+}
+impl Layouts {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let camera = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Camera layout"),
+            entries: &[uniform(0, RAYTRACE_STAGES), uniform(1, RAYTRACE_STAGES)]
+        });
+        let chunks = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Chunks layout"),
+            entries: &[
+                storage(0, RAYTRACE_STAGES),
+                uniform(1, RAYTRACE_STAGES),
+                storage(2, RAYTRACE_STAGES),
+                uniform(3, RAYTRACE_STAGES),
+                storage(4, RAYTRACE_STAGES)
+            ]
+        });
+        let output = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Output layout"),
+            entries: &[storage_texture(0, RENDER_TARGET_FORMAT)]
+        });
+        let gbuffer_output = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("G-buffer output layout"),
+            entries: &[
+                storage_texture(0, RENDER_TARGET_FORMAT),
+                storage_texture(1, DEPTH_FORMAT),
+                storage_texture(2, NORMAL_FORMAT),
+                storage_texture(3, MATERIAL_FORMAT)
+            ]
+        });
+        let upscale = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Upscale layout"),
+            entries: &[texture(0), sampler(1), uniform(2, wgpu::ShaderStages::FRAGMENT)]
+        });
+        let overlay = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Overlay layout"),
+            entries: &[texture(0), sampler(1)]
+        });
+        Self { camera, chunks, output, gbuffer_output, upscale, overlay }
+    }
+    pub fn raytrace(&self, device: &wgpu::Device, backend: RenderBackend, gbuffer: bool) -> wgpu::PipelineLayout {
+        let output = if gbuffer { &self.gbuffer_output } else { &self.output };
+        let bind_group_layouts = match backend {
+            RenderBackend::Fragment => vec![&self.camera, &self.chunks],
+            RenderBackend::Compute => vec![&self.camera, &self.chunks, output]
+        };
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("RayTraceShader layout"),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[]
+        })
+    }
+    pub fn upscale(&self, device: &wgpu::Device) -> wgpu::PipelineLayout {
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("UpscaleShader layout"),
+            bind_group_layouts: &[&self.upscale],
+            push_constant_ranges: &[]
+        })
+    }
+    pub fn overlay(&self, device: &wgpu::Device) -> wgpu::PipelineLayout {
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("OverlayShader layout"),
+            bind_group_layouts: &[&self.overlay],
+            push_constant_ranges: &[]
+        })
+    }
+}
+
+fn uniform(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None
+        },
+        count: None
+    }
+}
+
+fn storage(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None
+        },
+        count: None
+    }
+}
+
+fn texture(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false
+        },
+        count: None
+    }
+}
+
+fn sampler(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None
+    }
+}
+
+fn storage_texture(binding: u32, format: wgpu::TextureFormat) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format,
+            view_dimension: wgpu::TextureViewDimension::D2
+        },
+        count: None
+    }
+}
@@ -1,10 +1,25 @@
 mod engine;    pub use engine::*;
 mod utils;     pub use utils::*;
+mod error;     pub use error::*;
 mod window;    pub use window::*;
 mod settings;  pub use settings::*;
 mod context;   pub use context::*;
 mod camera;    pub use camera::*;
 mod chunks;    pub use chunks::*;
 mod chunk;     pub use chunk::*;
+mod render_target; pub use render_target::*;
+mod gbuffer;   pub use gbuffer::*;
+mod debug;     pub use debug::*;
+mod octree;    pub use octree::*;
+mod material;  pub use material::*;
+mod layouts;   pub use layouts::*;
+mod vox;       pub use vox::*;
+mod export;    pub use export::*;
+mod heightmap; pub use heightmap::*;
+mod world;     pub use world::*;
+mod headless;  pub use headless::*;
+mod profiler;  pub use profiler::*;
+mod ui;        pub use ui::*;
+mod console;   pub use console::*;
 
 pub mod shader;
\ No newline at end of file
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+// Material 0 is always empty space, the others map to an RGBA colour
+#[derive(Clone)]
+pub struct Materials {
+    pub colors: Vec<[u8;4]>,
+    lookup: HashMap<[u8;4], u32>
+}
+impl Materials {
+    pub fn new() -> Self {
+        Self {
+            colors: vec![[0;4]],
+            lookup: HashMap::new()
+        }
+    }
+    pub fn add(&mut self, color: [u8;4]) -> u32 {
+        if let Some(id) = self.lookup.get(&color) { return *id }
+        let id = self.colors.len() as u32;
+        self.colors.push(color);
+        self.lookup.insert(color, id);
+        id
+    }
+    pub fn get(&self, id: u32) -> Option<[u8;4]> {
+        if id == 0 { return None }
+        self.colors.get(id as usize).copied()
+    }
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.colors.len() <= 1
+    }
+    pub fn packed(&self) -> Vec<u32> {
+        self.colors.iter().map(|c| u32::from_le_bytes(*c)).collect()
+    }
+}
+impl Default for Materials {
+    fn default() -> Self {
+        Self::new()
+    }
+}
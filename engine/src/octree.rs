@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use crate::{Chunk, CHUNK_SIZE};
+
+pub const OCTREE_LEAF: u32 = 1 << 31;
+
+enum Node {
+    Leaf(u32),
+    Branch(Box<[Option<Node>;8]>)
+}
+
+// Nodes are pairs of words. A leaf is `[OCTREE_LEAF | material, 0]`, a branch is `[child_mask, first_child]`
+// with only its present children stored contiguously from `first_child`, in child-mask bit order.
+// Child `i` covers the octant with offset `(i & 1, (i >> 1) & 1, i >> 2)`.
+#[derive(Clone)]
+pub struct Octree {
+    pub origin: [i32;3],
+    pub size: u32,
+    pub nodes: Vec<[u32;2]>
+}
+impl Octree {
+    pub fn empty() -> Self {
+        Self { origin: [0;3], size: 0, nodes: vec![[0, 0]] }
+    }
+    /// Builds an octree over all `chunks`, whose positions are expected to lie on the 16-voxel chunk grid.
+    pub fn from_chunks(chunks: &[Chunk]) -> Self {
+        if chunks.is_empty() { return Self::empty() }
+        let mut grid = HashMap::new();
+        let mut min = [i32::MAX;3];
+        let mut max = [i32::MIN;3];
+        for chunk in chunks {
+            let key = chunk_key(chunk);
+            for i in 0..3 {
+                min[i] = min[i].min(key[i]);
+                max[i] = max[i].max(key[i]);
+            }
+            grid.insert(key, chunk);
+        }
+        let extent = (0..3).map(|i| max[i] - min[i] + 1).max().unwrap() as u32;
+        let size = extent.next_power_of_two() * CHUNK_SIZE as u32;
+        let origin = min.map(|v| v * CHUNK_SIZE as i32);
+
+        let builder = Builder { grid: &grid };
+        let mut nodes = vec![[0, 0]];
+        if let Some(root) = builder.build(origin, size) {
+            serialise(&root, 0, &mut nodes);
+        }
+        log::info!("Octree built from {} chunks: {} nodes, {} KiB (chunks: {} KiB)",
+            chunks.len(), nodes.len(), nodes.len() * 8 / 1024, std::mem::size_of_val(chunks) / 1024);
+        Self { origin, size, nodes }
+    }
+    pub fn get(&self, position: [i32;3]) -> u32 {
+        let mut local = [0;3];
+        for i in 0..3 {
+            local[i] = position[i] - self.origin[i];
+            if local[i] < 0 || local[i] >= self.size as i32 { return 0 }
+        }
+        let mut index = 0;
+        let mut size = self.size as i32;
+        loop {
+            let [word, first_child] = self.nodes[index];
+            if word & OCTREE_LEAF != 0 { return word & !OCTREE_LEAF }
+            size /= 2;
+            let child = (local[0] >= size) as u32 | ((local[1] >= size) as u32) << 1 | ((local[2] >= size) as u32) << 2;
+            if word & (1 << child) == 0 { return 0 }
+            index = (first_child + (word & ((1 << child) - 1)).count_ones()) as usize;
+            for l in local.iter_mut() { *l %= size }
+        }
+    }
+    pub fn info(&self) -> [f32;4] {
+        [self.origin[0] as f32, self.origin[1] as f32, self.origin[2] as f32, self.size as f32]
+    }
+}
+
+struct Builder<'a> {
+    grid: &'a HashMap<[i32;3], &'a Chunk>
+}
+impl Builder<'_> {
+    fn build(&self, min: [i32;3], size: u32) -> Option<Node> {
+        if size == 1 {
+            let material = self.voxel(min);
+            return (material != 0).then_some(Node::Leaf(material))
+        }
+        if size as usize >= CHUNK_SIZE && !self.overlaps_chunk(min, size) { return None }
+        let half = size as i32 / 2;
+        let children: [Option<Node>;8] = std::array::from_fn(|i| self.build(
+            [min[0] + (i as i32 & 1) * half, min[1] + (i as i32 >> 1 & 1) * half, min[2] + (i as i32 >> 2) * half],
+            size / 2
+        ));
+        if children.iter().all(Option::is_none) { return None }
+        if let Some(Node::Leaf(material)) = &children[0] {
+            if children.iter().all(|c| matches!(c, Some(Node::Leaf(m)) if m == material)) {
+                return Some(Node::Leaf(*material))
+            }
+        }
+        Some(Node::Branch(Box::new(children)))
+    }
+    fn voxel(&self, position: [i32;3]) -> u32 {
+        let key = position.map(|v| v.div_euclid(CHUNK_SIZE as i32));
+        match self.grid.get(&key) {
+            Some(chunk) => {
+                let [x, y, z] = position.map(|v| v.rem_euclid(CHUNK_SIZE as i32) as usize);
+                chunk.data[x][y][z] & !OCTREE_LEAF
+            }
+            None => 0
+        }
+    }
+    fn overlaps_chunk(&self, min: [i32;3], size: u32) -> bool {
+        let chunks = size as i32 / CHUNK_SIZE as i32;
+        let key = min.map(|v| v.div_euclid(CHUNK_SIZE as i32));
+        (0..chunks).any(|x| (0..chunks).any(|y| (0..chunks).any(|z|
+            self.grid.contains_key(&[key[0] + x, key[1] + y, key[2] + z]))))
+    }
+}
+
+fn chunk_key(chunk: &Chunk) -> [i32;3] {
+    [0, 1, 2].map(|i| (chunk.position[i].floor() as i32).div_euclid(CHUNK_SIZE as i32))
+}
+
+fn serialise(node: &Node, index: usize, nodes: &mut Vec<[u32;2]>) {
+    match node {
+        Node::Leaf(material) => nodes[index] = [OCTREE_LEAF | material, 0],
+        Node::Branch(children) => {
+            let mask = children.iter().enumerate().fold(0, |mask, (i, c)| mask | ((c.is_some() as u32) << i));
+            let first_child = nodes.len();
+            nodes.resize(first_child + mask.count_ones() as usize, [0, 0]);
+            nodes[index] = [mask, first_child as u32];
+            for (slot, child) in children.iter().flatten().enumerate() {
+                serialise(child, first_child + slot, nodes);
+            }
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::{io::{self, Write}, path::Path, sync::{Arc, Mutex, PoisonError, atomic::{AtomicBool, Ordering}}, time::Instant};
+
+use crate::{utils, Ui};
+
+// Frames the rolling statistics cover
+pub const STATS_FRAMES: usize = 120;
+// Frames kept for the CSV dump, about ten minutes at 60 fps
+const MAX_RECORDED_FRAMES: usize = 36000;
+const MAX_TIMESTAMPS: u32 = 16;
+
+/// Average, minimum and maximum of the last `capacity` samples.
+pub struct RollingStats {
+    samples: Vec<f32>,
+    next: usize,
+    capacity: usize
+}
+impl RollingStats {
+    pub fn new(capacity: usize) -> Self {
+        Self { samples: Vec::with_capacity(capacity), next: 0, capacity }
+    }
+    pub fn push(&mut self, value: f32) {
+        if self.samples.len() < self.capacity {
+            self.samples.push(value);
+        } else {
+            self.samples[self.next] = value;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+    pub fn last(&self) -> Option<f32> {
+        if self.samples.is_empty() { return None }
+        Some(self.samples[(self.next + self.capacity - 1) % self.capacity])
+    }
+    pub fn average(&self) -> f32 {
+        if self.samples.is_empty() { return 0. }
+        self.samples.iter().sum::<f32>() / self.samples.len() as f32
+    }
+    pub fn min(&self) -> f32 {
+        self.samples.iter().copied().reduce(f32::min).unwrap_or(0.)
+    }
+    pub fn max(&self) -> f32 {
+        self.samples.iter().copied().reduce(f32::max).unwrap_or(0.)
+    }
+}
+
+/// Per stage timings in milliseconds, with rolling statistics and every frame kept for a CSV dump.
+/// Stages are listed in the order they were first recorded.
+#[derive(Default)]
+pub struct Timings {
+    pub stats: Vec<(&'static str, RollingStats)>,
+    frames: Vec<Vec<(&'static str, f32)>>,
+    frame: Vec<(&'static str, f32)>
+}
+impl Timings {
+    pub fn record(&mut self, stage: &'static str, ms: f32) {
+        self.record_in(self.frames.len(), stage, ms);
+    }
+    /// Records a stage of an earlier frame, given by the `frame_index` it had, such as GPU timings read back
+    /// after the frame ended.
+    pub fn record_in(&mut self, frame: usize, stage: &'static str, ms: f32) {
+        match self.stats.iter_mut().find(|(name, _)| *name == stage) {
+            Some((_, stats)) => stats.push(ms),
+            None => {
+                let mut stats = RollingStats::new(STATS_FRAMES);
+                stats.push(ms);
+                self.stats.push((stage, stats));
+            }
+        }
+        if frame == self.frames.len() {
+            self.frame.push((stage, ms));
+        } else if let Some(row) = self.frames.get_mut(frame) {
+            row.push((stage, ms));
+        }
+    }
+    /// The row the stages recorded now go to.
+    pub fn frame_index(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn end_frame(&mut self) {
+        if self.frames.len() < MAX_RECORDED_FRAMES {
+            self.frames.push(std::mem::take(&mut self.frame));
+        } else {
+            self.frame.clear();
+        }
+    }
+    pub fn frames(&self) -> usize {
+        self.frames.len()
+    }
+    /// One row per frame and one column per stage, left empty when a stage did not run that frame.
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        let stages: Vec<&str> = self.stats.iter().map(|(name, _)| *name).collect();
+        writeln!(writer, "frame,{}", stages.join(","))?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            let columns: Vec<String> = stages.iter().map(|stage| {
+                frame.iter().filter(|(name, _)| name == stage).map(|(_, ms)| *ms).reduce(|a, b| a + b)
+                    .map_or(String::new(), |ms| format!("{ms:.3}"))
+            }).collect();
+            writeln!(writer, "{i},{}", columns.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+// Timestamps written between the passes of a frame, read back a frame or more later without stalling
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    read_buffer: wgpu::Buffer,
+    // Nanoseconds per timestamp tick
+    period: f32,
+    labels: Vec<&'static str>,
+    // Row of the timings the timestamps belong to, None once they are from before the profiler restarted
+    frame: Option<usize>,
+    active: bool,
+    pending: Option<Vec<&'static str>>,
+    mapped: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>
+}
+
+/// CPU timings of the frame stages and, when the adapter supports timestamp queries, GPU timings of each
+/// pass. Nothing is measured until it is enabled.
+pub struct Profiler {
+    enabled: AtomicBool,
+    pub timings: Mutex<Timings>,
+    frame_start: Mutex<Option<Instant>>,
+    frame_end: Mutex<Option<Instant>>,
+    gpu: Option<Mutex<GpuTimer>>
+}
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let gpu = device.features().contains(wgpu::Features::TIMESTAMP_QUERY).then(|| {
+            let size = MAX_TIMESTAMPS as u64 * 8;
+            Mutex::new(GpuTimer {
+                query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Profiler timestamps"),
+                    ty: wgpu::QueryType::Timestamp,
+                    count: MAX_TIMESTAMPS
+                }),
+                read_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler readback"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false
+                }),
+                period: queue.get_timestamp_period(),
+                labels: vec![],
+                frame: None,
+                active: false,
+                pending: None,
+                mapped: Arc::new(Mutex::new(None))
+            })
+        });
+        if gpu.is_none() {
+            log::info!("Timestamp queries are not supported, the profiler only measures CPU time");
+        }
+        Self {
+            enabled: AtomicBool::new(false),
+            timings: Mutex::new(Timings::default()),
+            frame_start: Mutex::new(None),
+            frame_end: Mutex::new(None),
+            gpu
+        }
+    }
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+    /// Starts measuring with fresh timings, or stops and writes what was measured to a CSV file in the log
+    /// directory.
+    pub fn toggle(&self) {
+        if !self.enabled() {
+            *self.timings.lock().unwrap() = Timings::default();
+            *self.frame_start.lock().unwrap() = None;
+            *self.frame_end.lock().unwrap() = None;
+            if let Some(gpu) = &self.gpu {
+                gpu.lock().unwrap().frame = None;
+            }
+            self.enabled.store(true, Ordering::Relaxed);
+            return log::info!("Profiler started")
+        }
+        self.stop();
+    }
+    pub fn stop(&self) {
+        if !self.enabled.swap(false, Ordering::Relaxed) { return }
+        let path = utils::data_dir().join(format!("profile-{}.csv", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+        match self.write_csv(&path) {
+            Ok(()) => log::info!("Profiler stopped, {} frames written to {path:?}", self.timings.lock().unwrap_or_else(PoisonError::into_inner).frames()),
+            Err(e) => log::error!("Failed to write the profile to {path:?}: {e}")
+        }
+    }
+    pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+        // Still written after a panic while timing, such as when the device was lost
+        self.timings.lock().unwrap_or_else(PoisonError::into_inner).write_csv(&mut file)?;
+        file.flush()
+    }
+    /// Closes the previous frame, counting the time since its `end_frame` as the event loop, and collects
+    /// GPU timings that became available into the row of the frame they were measured in.
+    pub fn begin_frame(&self, device: &wgpu::Device) {
+        if !self.enabled() { return }
+        let now = Instant::now();
+        let mut timings = self.timings.lock().unwrap();
+        if let Some(gpu) = &self.gpu {
+            let mut gpu = gpu.lock().unwrap();
+            let frame = gpu.frame;
+            for (label, ms) in gpu.read(device) {
+                if let Some(frame) = frame { timings.record_in(frame, label, ms) }
+            }
+        }
+        if let Some(end) = self.frame_end.lock().unwrap().take() {
+            timings.record("cpu event loop", (now - end).as_secs_f32() * 1000.);
+        }
+        if let Some(start) = self.frame_start.lock().unwrap().replace(now) {
+            timings.record("cpu frame", (now - start).as_secs_f32() * 1000.);
+            timings.end_frame();
+        }
+    }
+    pub fn end_frame(&self) {
+        if !self.enabled() { return }
+        *self.frame_end.lock().unwrap() = Some(Instant::now());
+    }
+    pub fn time<T>(&self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+        if !self.enabled() { return f() }
+        let start = Instant::now();
+        let result = f();
+        self.timings.lock().unwrap().record(stage, start.elapsed().as_secs_f32() * 1000.);
+        result
+    }
+    /// Marks the start of the GPU work of a frame, skipped while the previous timings are still in flight.
+    pub fn begin_gpu(&self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(gpu) = &self.gpu else { return };
+        let frame = self.timings.lock().unwrap().frame_index();
+        let mut gpu = gpu.lock().unwrap();
+        gpu.active = self.enabled() && gpu.pending.is_none();
+        if !gpu.active { return }
+        gpu.labels.clear();
+        gpu.frame = Some(frame);
+        encoder.write_timestamp(&gpu.query_set, 0);
+    }
+    /// Ends the GPU span `label`, which started at the previous span or `begin_gpu`.
+    pub fn gpu_span(&self, encoder: &mut wgpu::CommandEncoder, label: &'static str) {
+        let Some(gpu) = &self.gpu else { return };
+        let mut gpu = gpu.lock().unwrap();
+        if !gpu.active || gpu.labels.len() as u32 + 1 >= MAX_TIMESTAMPS { return }
+        gpu.labels.push(label);
+        encoder.write_timestamp(&gpu.query_set, gpu.labels.len() as u32);
+    }
+    /// Resolves the timestamps of this frame into the readback buffer, to be called last in the encoder.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(gpu) = &self.gpu else { return };
+        let gpu = gpu.lock().unwrap();
+        if !gpu.active || gpu.labels.is_empty() { return }
+        let count = gpu.labels.len() as u32 + 1;
+        encoder.resolve_query_set(&gpu.query_set, 0..count, &gpu.read_buffer, 0);
+    }
+    /// Starts mapping the readback buffer once the frame was submitted.
+    pub fn after_submit(&self) {
+        let Some(gpu) = &self.gpu else { return };
+        let mut gpu = gpu.lock().unwrap();
+        if !gpu.active || gpu.labels.is_empty() { return }
+        gpu.active = false;
+        gpu.pending = Some(gpu.labels.clone());
+        let mapped = gpu.mapped.clone();
+        gpu.read_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| *mapped.lock().unwrap() = Some(result));
+    }
+    /// A row per stage with its average and maximum time and a bar of the average with a tick at the
+    /// maximum, scaled so the target frame time is half the bar width.
+    pub fn draw_overlay(&self, ui: &Ui, target_frame_time: f32) {
+        if !self.enabled() { return }
+        const ORIGIN: [f32;2] = [8., 8.];
+        const WIDTH: f32 = 320.;
+        let row = crate::GLYPH_CELL[1] as f32 * ui.scale;
+        let scale = WIDTH / (target_frame_time * 2.);
+        let timings = self.timings.lock().unwrap();
+        let labels: Vec<String> = timings.stats.iter()
+            .map(|(stage, stats)| format!("{stage:<17}{:>6.2} {:>6.2}", stats.average(), stats.max()))
+            .collect();
+        let bar_x = ORIGIN[0] + crate::text_size(&labels.join("\n"), ui.scale)[0] + 8.;
+        let height = timings.stats.len() as f32 * row;
+        ui.rect([ORIGIN[0] - 4., ORIGIN[1] - 4.], [bar_x + WIDTH + 4., ORIGIN[1] + height + 2.], [0., 0., 0., 0.6]);
+        for (i, ((_, stats), label)) in timings.stats.iter().zip(&labels).enumerate() {
+            let y = ORIGIN[1] + i as f32 * row;
+            let [r, g, b] = stage_colour(i);
+            ui.text_coloured([ORIGIN[0], y], [r, g, b, 1.], label);
+            ui.rect([bar_x, y], [bar_x + (stats.average() * scale).min(WIDTH), y + row - 2.], [r, g, b, 1.]);
+            let max = bar_x + (stats.max() * scale).min(WIDTH - 2.);
+            ui.rect([max, y], [max + 2., y + row - 2.], [1., 1., 1., 0.8]);
+        }
+        let target = bar_x + WIDTH / 2.;
+        ui.rect([target - 1., ORIGIN[1]], [target + 1., ORIGIN[1] + height], [1., 0.2, 0.2, 0.8]);
+    }
+}
+impl GpuTimer {
+    fn read(&mut self, device: &wgpu::Device) -> Vec<(&'static str, f32)> {
+        if self.pending.is_none() { return vec![] }
+        device.poll(wgpu::Maintain::Poll);
+        let Some(result) = self.mapped.lock().unwrap().take() else { return vec![] };
+        let labels = self.pending.take().unwrap();
+        if let Err(e) = result {
+            log::warn!("Failed to read GPU timestamps: {e}");
+            return vec![]
+        }
+        let size = (labels.len() as u64 + 1) * 8;
+        let timestamps: Vec<u64> = bytemuck::cast_slice(&self.read_buffer.slice(..size).get_mapped_range()).to_vec();
+        self.read_buffer.unmap();
+        labels.into_iter().zip(timestamps.windows(2))
+            .map(|(label, span)| (label, span[1].saturating_sub(span[0]) as f32 * self.period / 1_000_000.))
+            .collect()
+    }
+}
+
+fn stage_colour(i: usize) -> [f32;3] {
+    const PALETTE: [[f32;3];8] = [
+        [0.9, 0.6, 0.1], [0.3, 0.7, 0.9], [0.5, 0.8, 0.3], [0.8, 0.4, 0.8],
+        [0.9, 0.9, 0.3], [0.3, 0.9, 0.7], [0.9, 0.5, 0.5], [0.6, 0.6, 0.9]
+    ];
+    PALETTE[i % PALETTE.len()]
+}
@@ -0,0 +1,212 @@
+use std::{sync::Mutex, time::Instant};
+
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::{Settings, GBuffer, Layouts, RenderBackend, MAX_RENDER_SCALE, MIN_RENDER_SCALE};
+
+pub const RENDER_TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+const SCALE_STEP: f32 = 0.05;
+const FRAMES_PER_ADJUSTMENT: u32 = 30;
+
+struct FrameTimer {
+    last_frame: Instant,
+    average: f32,
+    frames: u32
+}
+
+pub struct RenderTarget {
+    pub scale: Mutex<f32>,
+    pub size: Mutex<PhysicalSize<u32>>,
+    pub texture: Mutex<wgpu::Texture>,
+    pub view: Mutex<wgpu::TextureView>,
+    pub bind_group: Mutex<wgpu::BindGroup>,
+    pub storage_bind_group: Mutex<Option<wgpu::BindGroup>>,
+    pub gbuffer: Mutex<Option<GBuffer>>,
+    pub upscale_buffer: wgpu::Buffer,
+    backend: RenderBackend,
+    sampler: wgpu::Sampler,
+    timer: Mutex<FrameTimer>
+}
+impl RenderTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        layouts: &Layouts,
+        backend: RenderBackend,
+        settings: &Settings,
+        surface_size: PhysicalSize<u32>,
+        linear_output: bool
+    ) -> Self {
+        let scale = settings.render_scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        let size = scaled_size(surface_size, scale);
+        let (texture, view) = create_texture(device, size);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Upscale sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let upscale_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&[settings.upscale_sharpness, linear_output as u32 as f32, 0., 0.]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+            }
+        );
+        let bind_group = create_bind_group(device, layouts, &view, &sampler, &upscale_buffer);
+        let gbuffer = settings.gbuffer.then(|| GBuffer::new(device, size));
+        let storage_bind_group = create_storage_bind_group(device, layouts, backend, &view, gbuffer.as_ref());
+        log::info!("Render target: {}x{} (scale {scale})", size.width, size.height);
+        Self {
+            scale: Mutex::new(scale),
+            size: Mutex::new(size),
+            texture: Mutex::new(texture),
+            view: Mutex::new(view),
+            bind_group: Mutex::new(bind_group),
+            storage_bind_group: Mutex::new(storage_bind_group),
+            gbuffer: Mutex::new(gbuffer),
+            upscale_buffer,
+            backend,
+            sampler,
+            timer: Mutex::new(FrameTimer {
+                last_frame: Instant::now(),
+                average: settings.target_frame_time,
+                frames: 0
+            })
+        }
+    }
+    pub fn resize(
+        &self,
+        device: &wgpu::Device,
+        layouts: &Layouts,
+        surface_size: PhysicalSize<u32>
+    ) -> PhysicalSize<u32> {
+        let size = scaled_size(surface_size, *self.scale.lock().unwrap());
+        let mut current_size = self.size.lock().unwrap();
+        if *current_size == size { return size }
+        let (texture, view) = create_texture(device, size);
+        *self.bind_group.lock().unwrap() = create_bind_group(device, layouts, &view, &self.sampler, &self.upscale_buffer);
+        let mut gbuffer = self.gbuffer.lock().unwrap();
+        if gbuffer.is_some() {
+            *gbuffer = Some(GBuffer::new(device, size));
+        }
+        *self.storage_bind_group.lock().unwrap() = create_storage_bind_group(device, layouts, self.backend, &view, gbuffer.as_ref());
+        *self.texture.lock().unwrap() = texture;
+        *self.view.lock().unwrap() = view;
+        *current_size = size;
+        size
+    }
+    /// Measures the time since the previous frame and, with dynamic resolution enabled, steps the
+    /// render scale towards the target frame time. Returns the new target size when it changed.
+    pub fn update(
+        &self,
+        device: &wgpu::Device,
+        layouts: &Layouts,
+        settings: &Settings,
+        surface_size: PhysicalSize<u32>
+    ) -> Option<PhysicalSize<u32>> {
+        let mut timer = self.timer.lock().unwrap();
+        let now = Instant::now();
+        let frame_time = now.duration_since(timer.last_frame).as_secs_f32() * 1000.;
+        timer.last_frame = now;
+        timer.average += (frame_time - timer.average) * 0.1;
+        timer.frames += 1;
+
+        if !settings.dynamic_resolution || timer.frames < FRAMES_PER_ADJUSTMENT { return None }
+        timer.frames = 0;
+
+        let max_scale = settings.render_scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        let min_scale = settings.min_render_scale.clamp(MIN_RENDER_SCALE, max_scale);
+        let mut scale = self.scale.lock().unwrap();
+        let new_scale = if timer.average > settings.target_frame_time * 1.05 {
+            (*scale - SCALE_STEP).max(min_scale)
+        } else if timer.average < settings.target_frame_time * 0.85 {
+            (*scale + SCALE_STEP).min(max_scale)
+        } else {
+            return None
+        };
+        if new_scale == *scale { return None }
+        log::trace!("Render scale {} -> {new_scale} ({:.2}ms average frame time)", *scale, timer.average);
+        *scale = new_scale;
+        drop(scale);
+        Some(self.resize(device, layouts, surface_size))
+    }
+}
+
+pub fn scaled_size(surface_size: PhysicalSize<u32>, scale: f32) -> PhysicalSize<u32> {
+    PhysicalSize::new(
+        ((surface_size.width as f32 * scale) as u32).max(1),
+        ((surface_size.height as f32 * scale) as u32).max(1)
+    )
+}
+
+fn create_texture(device: &wgpu::Device, size: PhysicalSize<u32>) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Render target"),
+        size: wgpu::Extent3d { width: size.width, height: size.height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: RENDER_TARGET_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layouts: &Layouts,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+    upscale_buffer: &wgpu::Buffer
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &layouts.upscale,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view)
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler)
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: upscale_buffer.as_entire_binding()
+            }
+        ]
+    })
+}
+
+fn create_storage_bind_group(
+    device: &wgpu::Device,
+    layouts: &Layouts,
+    backend: RenderBackend,
+    view: &wgpu::TextureView,
+    gbuffer: Option<&GBuffer>
+) -> Option<wgpu::BindGroup> {
+    if backend != RenderBackend::Compute { return None }
+    let mut entries = vec![
+        wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(view)
+        }
+    ];
+    if let Some(gbuffer) = gbuffer {
+        for (binding, texture) in [(1, &gbuffer.depth), (2, &gbuffer.normal), (3, &gbuffer.material)] {
+            entries.push(wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&texture.view)
+            });
+        }
+    }
+    Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: if gbuffer.is_some() { &layouts.gbuffer_output } else { &layouts.output },
+        entries: &entries
+    }))
+}
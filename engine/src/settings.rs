@@ -1,57 +1,307 @@
-use std::io::Read;
+use std::{io::{self, ErrorKind}, path::{Path, PathBuf}};
 
-use directories::UserDirs;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-#[derive(Serialize, Deserialize)]
+use crate::utils;
+
+// Bumped whenever a change to the fields needs a migration of existing files
+pub const SETTINGS_VERSION: u32 = 3;
+
+// Bounds of render_scale and min_render_scale
+pub const MIN_RENDER_SCALE: f32 = 0.1;
+pub const MAX_RENDER_SCALE: f32 = 2.;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderBackend {
+    Fragment,
+    Compute
+}
+
+// Fifo waits for vertical blank, Mailbox replaces the queued frame without tearing and Immediate tears
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PresentMode {
+    Fifo,
+    Mailbox,
+    Immediate
+}
+
+// Srgb lets the hardware encode gamma, Linear has the upscale pass do it and Hdr asks for an Rgba16Float surface
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SurfaceFormat {
+    Srgb,
+    Linear,
+    Hdr
+}
+
+// The wgpu backend adapters are picked from, Auto tries all of them
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GraphicsBackend {
+    Auto,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct Settings {
+    pub version: u32,
     pub window_size: Option<[u32;2]>,
     pub window_fullscreen: bool,
     pub window_decorations: bool,
     pub window_maximized: bool,
-    pub window_position: Option<[u32;2]>,
-    pub vsync: bool,
+    // Negative on monitors left of or above the primary one
+    pub window_position: Option<[i32;2]>,
+    pub present_mode: PresentMode,
+    pub surface_format: SurfaceFormat,
     pub fov: f32,
     pub near: f32,
-    pub far: f32
+    pub far: f32,
+    pub render_scale: f32,
+    pub dynamic_resolution: bool,
+    pub min_render_scale: f32,
+    pub target_frame_time: f32,
+    pub upscale_sharpness: f32,
+    pub render_backend: RenderBackend,
+    pub gbuffer: bool,
+    pub lod_distance: f32,
+    // Loads the world into a sparse voxel octree instead of chunks, for large static maps and scans
+    pub octree_world: bool,
+    pub world_path: Option<PathBuf>,
+    pub seed: u32,
+    pub shader_hot_reload: bool,
+    pub graphics_backend: GraphicsBackend,
+    // Adapters whose name contains this, ignoring case, are preferred
+    pub adapter_name: Option<String>,
+    pub force_fallback_adapter: bool,
+    // In env_logger syntax, RUST_LOG replaces it when set
+    pub log_filter: String,
+    // Session logs kept in the log directory
+    pub log_history: u32,
+    // Also writes the log as JSON lines next to the text log
+    pub json_log: bool
 }
 impl Settings {
+    pub fn path() -> PathBuf {
+        utils::config_dir().join("settings.json")
+    }
+    /// Never fails: a missing file is created, and one that cannot be parsed is backed up and replaced
+    /// with the defaults.
     pub fn read() -> Self {
-        let user_dir = UserDirs::new().expect("Failed to get user directory");
-        let doc_dir = user_dir.document_dir().expect("Failed to get user document directory");
-        let set_dir = doc_dir.join(env!("DOC_PATH"));
-        let set_path = set_dir.join("settings.json");
-        log::info!("Settings path: {set_path:?}");
-
-        std::fs::create_dir_all(&set_dir).expect(&format!("Error creating directory: {set_dir:?}", ));
-        
-        let mut file = std::fs::OpenOptions::new().create(true).read(true).write(true).truncate(false).open(&set_path)
-            .expect(&format!("Failed to open path: {set_path:?}"));
-        
-        let mut content = String::new();
-        file.read_to_string(&mut content).expect(&format!("Error reading file: {set_path:?}"));
-
-        if content.len() == 0 {
+        let path = Self::path();
+        log::info!("Settings path: {path:?}");
+        let content = match std::fs::read_to_string(&path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                log::error!("Failed to read settings {path:?}, using the defaults: {e}");
+                return Self::default()
+            }
+        };
+        if content.trim().is_empty() {
             let default = Self::default();
-            serde_json::to_writer_pretty(&mut file, &default).unwrap();
+            default.write(&path);
             return default
-        }else {
-            serde_json::from_str(&content).unwrap()
+        }
+
+        match Self::parse(&content) {
+            Ok(settings) => {
+                // Rewrite the file if it was migrated, clamped or missing fields so it shows what is in use,
+                // leaving files from newer versions alone. Compared through text so f32 fields round trip exactly
+                let current = serde_json::to_string(&settings).unwrap();
+                if settings.version <= SETTINGS_VERSION && serde_json::from_str::<Value>(&content).ok() != serde_json::from_str(&current).ok() {
+                    settings.write(&path);
+                }
+                settings
+            }
+            Err(e) => {
+                let backup = path.with_file_name(format!("settings.{}.json.bak", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+                match std::fs::rename(&path, &backup) {
+                    Ok(()) => log::warn!("Invalid settings file {path:?}: {e}. It was moved to {backup:?} and the defaults restored"),
+                    Err(rename_error) => log::warn!("Invalid settings file {path:?}: {e}. Using the defaults, the backup to {backup:?} failed: {rename_error}")
+                }
+                let default = Self::default();
+                default.write(&path);
+                default
+            }
+        }
+    }
+    /// Parses settings of any earlier version, filling in missing fields, using the defaults for invalid
+    /// ones and clamping out of range values.
+    pub fn parse(content: &str) -> Result<Self, serde_json::Error> {
+        let mut value: Value = serde_json::from_str(content)?;
+        migrate(&mut value);
+        reset_invalid_fields(&mut value);
+        let mut settings: Self = serde_json::from_value(value)?;
+        settings.validate();
+        Ok(settings)
+    }
+    pub fn validate(&mut self) {
+        fn clamp(name: &str, value: &mut f32, min: f32, max: f32) {
+            let clamped = if value.is_nan() { min } else { value.clamp(min, max) };
+            if clamped != *value {
+                log::warn!("Setting {name} = {value} is out of range, using {clamped}");
+                *value = clamped;
+            }
+        }
+        if self.window_size.is_some_and(|[width, height]| width == 0 || height == 0) {
+            log::warn!("Setting window_size = {:?} is empty, using the default size", self.window_size);
+            self.window_size = None;
+        }
+        clamp("fov", &mut self.fov, 1., 179.);
+        clamp("near", &mut self.near, 0.001, f32::MAX);
+        clamp("far", &mut self.far, self.near, f32::MAX);
+        clamp("render_scale", &mut self.render_scale, MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        clamp("min_render_scale", &mut self.min_render_scale, MIN_RENDER_SCALE, self.render_scale);
+        clamp("target_frame_time", &mut self.target_frame_time, 1., 1000.);
+        clamp("upscale_sharpness", &mut self.upscale_sharpness, 0., 1.);
+        clamp("lod_distance", &mut self.lod_distance, 0., f32::MAX);
+    }
+    /// The value of the field `key` as JSON.
+    pub fn field(&self, key: &str) -> Option<Value> {
+        serde_json::to_value(self).unwrap().get(key).cloned()
+    }
+    /// A copy with the field `key` set to `value`, read as JSON or otherwise as a string, then validated.
+    pub fn with_field(&self, key: &str, value: &str) -> Result<Self, String> {
+        let mut object = serde_json::to_value(self).unwrap();
+        let Some(field) = object.get_mut(key) else { return Err(format!("Unknown setting {key}")) };
+        *field = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.into()));
+        let mut settings: Self = serde_json::from_value(object).map_err(|e| format!("Invalid value for {key}: {e}"))?;
+        settings.validate();
+        Ok(settings)
+    }
+    /// A copy with the fields named in `keys` taken from `other`.
+    pub fn with_fields_from<'a>(&self, other: &Settings, keys: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut object = serde_json::to_value(self).unwrap();
+        let other = serde_json::to_value(other).unwrap();
+        for key in keys {
+            if let (Some(field), Some(value)) = (object.get_mut(key), other.get(key)) {
+                *field = value.clone();
+            }
+        }
+        serde_json::from_value(object).unwrap()
+    }
+    /// A copy with the fields that apply while running taken from `file`, keeping the rest, such as
+    /// overrides from the command line, as they are.
+    pub fn with_hot_reloadable(&self, file: &Settings) -> Self {
+        let mut settings = self.clone();
+        settings.present_mode = file.present_mode;
+        settings.fov = file.fov;
+        settings.render_scale = file.render_scale;
+        settings.dynamic_resolution = file.dynamic_resolution;
+        settings.min_render_scale = file.min_render_scale;
+        settings.target_frame_time = file.target_frame_time;
+        settings.upscale_sharpness = file.upscale_sharpness;
+        settings.log_filter = file.log_filter.clone();
+        settings.json_log = file.json_log;
+        settings.validate();
+        settings
+    }
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(&Self::path())
+    }
+    /// Writes to a temporary file first and renames it over `path`, so a crash mid-write never leaves a
+    /// truncated settings file behind.
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temporary = path.with_extension("json.tmp");
+        std::fs::write(&temporary, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&temporary, path)
+    }
+    fn write(&self, path: &Path) {
+        if let Err(e) = self.save_to(path) {
+            log::error!("Failed to write settings {path:?}: {e}")
         }
     }
 }
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            version: SETTINGS_VERSION,
             window_size: None,
             window_position: None,
             window_fullscreen: false,
             window_decorations: true,
             window_maximized: true,
-            vsync: true,
+            present_mode: PresentMode::Fifo,
+            surface_format: SurfaceFormat::Srgb,
             fov: 90.,
             near: 1.,
-            far: 100.
+            far: 100.,
+            render_scale: 1.,
+            dynamic_resolution: false,
+            min_render_scale: 0.5,
+            target_frame_time: 16.6,
+            upscale_sharpness: 0.,
+            render_backend: RenderBackend::Fragment,
+            gbuffer: false,
+            lod_distance: 64.,
+            octree_world: false,
+            world_path: None,
+            seed: 0,
+            shader_hot_reload: false,
+            graphics_backend: GraphicsBackend::Auto,
+            adapter_name: None,
+            force_fallback_adapter: false,
+            log_filter: utils::DEFAULT_LOG_FILTER.into(),
+            log_history: 10,
+            json_log: false
+        }
+    }
+}
+
+// One step per version, upgrading the fields of that version to the next
+const MIGRATIONS: [fn(&mut Map<String, Value>); SETTINGS_VERSION as usize] = [
+    // Only added fields, which take their defaults
+    |_| {},
+    // vsync became present_mode
+    |object| {
+        if let Some(vsync) = object.remove("vsync").and_then(|v| v.as_bool()) {
+            object.insert("present_mode".into(), if vsync { "Fifo" } else { "Immediate" }.into());
+        }
+    },
+    // window_position became signed
+    |object| {
+        let fits = object.get("window_position").and_then(Value::as_array)
+            .is_some_and(|position| position.iter().all(|v| v.as_i64().is_some_and(|v| i32::try_from(v).is_ok())));
+        if !fits {
+            object.remove("window_position");
         }
     }
-}
\ No newline at end of file
+];
+
+// Upgrades the raw JSON one version at a time, files from before the version field count as version 0
+fn migrate(value: &mut Value) {
+    let Some(object) = value.as_object_mut() else { return };
+    let version = object.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+    if version > SETTINGS_VERSION {
+        return log::warn!("Settings version {version} is newer than {SETTINGS_VERSION}, unknown fields are ignored")
+    }
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        step(object);
+        log::info!("Migrated settings from version {from} to {}", from + 1);
+    }
+    object.insert("version".into(), SETTINGS_VERSION.into());
+}
+
+// Removes fields that don't parse, such as an unknown enum variant, so that they take their default
+// instead of rejecting the whole file
+fn reset_invalid_fields(value: &mut Value) {
+    let Some(object) = value.as_object_mut() else { return };
+    let default = serde_json::to_value(Settings::default()).unwrap();
+    object.retain(|key, field| {
+        let mut single = default.clone();
+        let Some(slot) = single.get_mut(key) else { return true };
+        *slot = field.clone();
+        let valid = serde_json::from_value::<Settings>(single).is_ok();
+        if !valid {
+            log::warn!("Setting {key} = {field} is invalid, using the default");
+        }
+        valid
+    });
+}
@@ -0,0 +1,97 @@
+@group(2) @binding(0)
+var output: texture_storage_2d<rgba8unorm, write>;
+@group(2) @binding(1)
+var depth_output: texture_storage_2d<r32float, write>;
+@group(2) @binding(2)
+var normal_output: texture_storage_2d<rgba16float, write>;
+@group(2) @binding(3)
+var material_output: texture_storage_2d<r32uint, write>;
+
+var<workgroup> tile_chunks: array<u32, 256>;
+var<workgroup> tile_chunks_length: atomic<u32>;
+
+fn tile_cone_hits_chunk(origin: vec3<f32>, axis: vec3<f32>, spread: f32, chunk_id: u32) -> bool {
+    let to_centre = chunks[chunk_id].position.xyz + 8. - origin;
+    let distance = length(to_centre);
+    let radius = 13.86;
+    if distance <= radius { return true; }
+    let angle = acos(clamp(dot(to_centre, axis) / distance, -1., 1.));
+    return angle <= spread + asin(radius / distance);
+}
+
+struct TileSample {
+    inside: bool,
+    dir: vec3<f32>,
+    hit: Hit
+};
+
+fn trace_tile(global_id: vec3<u32>, local_index: u32, workgroup_id: vec3<u32>) -> TileSample {
+    var sample: TileSample;
+    let size = vec2<u32>(textureDimensions(output));
+    if local_index == 0u {
+        atomicStore(&tile_chunks_length, 0u);
+    }
+    workgroupBarrier();
+
+    // Cull chunks against the cone enclosing every ray of this 8x8 tile, cooperatively
+    let tile_min = vec2<f32>(workgroup_id.xy * 8u);
+    let tile_max = min(tile_min + 8., vec2<f32>(size));
+    let origin = camera.position.xyz;
+    let axis = ray_direction(camera_generate_ray(screen_coord((tile_min + tile_max) * 0.5)));
+    var spread = 0.;
+    for (var corner = 0u; corner < 4u; corner++) {
+        let pixel = select(tile_min, tile_max, vec2<bool>((corner & 1u) != 0u, (corner & 2u) != 0u));
+        let dir = ray_direction(camera_generate_ray(screen_coord(pixel)));
+        spread = max(spread, acos(clamp(dot(dir, axis), -1., 1.)));
+    }
+    for (var chunk_id = local_index; chunk_id < chunks_length.x; chunk_id += 64u) {
+        if tile_cone_hits_chunk(origin, axis, spread, chunk_id) {
+            let slot = atomicAdd(&tile_chunks_length, 1u);
+            if slot < 256u {
+                tile_chunks[slot] = chunk_id;
+            }
+        }
+    }
+    workgroupBarrier();
+
+    sample.inside = all(global_id.xy < size);
+    sample.hit = no_hit();
+    if !sample.inside { return sample; }
+    sample.dir = ray_direction(camera_generate_ray(screen_coord(vec2<f32>(global_id.xy) + 0.5)));
+    let length = atomicLoad(&tile_chunks_length);
+    if length > 256u {
+        sample.hit = trace(origin, sample.dir);
+    } else {
+        for (var i = 0u; i < length; i++) {
+            trace_chunk(tile_chunks[i], origin, sample.dir, &sample.hit);
+        }
+        trace_octree(origin, sample.dir, &sample.hit);
+    }
+    return sample;
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>
+) {
+    let sample = trace_tile(global_id, local_index, workgroup_id);
+    if !sample.inside { return; }
+    textureStore(output, vec2<i32>(global_id.xy), shade(sample.hit, sample.dir));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_gbuffer(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>
+) {
+    let sample = trace_tile(global_id, local_index, workgroup_id);
+    if !sample.inside { return; }
+    let coord = vec2<i32>(global_id.xy);
+    textureStore(output, coord, shade(sample.hit, sample.dir));
+    textureStore(depth_output, coord, vec4<f32>(linear_depth(sample.hit, sample.dir)));
+    textureStore(normal_output, coord, vec4<f32>(sample.hit.normal, f32(sample.hit.material != 0u)));
+    textureStore(material_output, coord, vec4<u32>(sample.hit.material));
+}
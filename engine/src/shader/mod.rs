@@ -1,15 +1,91 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, path::{Path, PathBuf}};
 
-use crate::Context;
+use futures::executor::block_on;
 
-pub fn new(device: &wgpu::Device, surface_format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+use crate::{Context, Layouts, RenderBackend, DEPTH_FORMAT, NORMAL_FORMAT, MATERIAL_FORMAT};
+
+pub mod upscale;
+pub mod overlay;
+
+const FRAGMENT_SOURCE: &str = concat!(include_str!("trace.wgsl"), include_str!("shader.wgsl"));
+const COMPUTE_SOURCE: &str = concat!(include_str!("trace.wgsl"), include_str!("compute.wgsl"));
+// Where the embedded sources live, for reloading them while developing
+pub const SOURCE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader");
+const FRAGMENT_FILES: [&str;2] = ["trace.wgsl", "shader.wgsl"];
+const COMPUTE_FILES: [&str;2] = ["trace.wgsl", "compute.wgsl"];
+
+pub const TILE_SIZE: u32 = 8;
+
+pub enum Raytracer {
+    Fragment(wgpu::RenderPipeline),
+    Compute(wgpu::ComputePipeline)
+}
+impl Raytracer {
+    pub fn backend(&self) -> RenderBackend {
+        match self {
+            Self::Fragment(_) => RenderBackend::Fragment,
+            Self::Compute(_) => RenderBackend::Compute
+        }
+    }
+}
+
+pub fn new(device: &wgpu::Device, layouts: &Layouts, backend: RenderBackend, target_format: wgpu::TextureFormat, gbuffer: bool) -> Raytracer {
+    let layout = layouts.raytrace(device, backend, gbuffer);
+    match backend {
+        RenderBackend::Fragment => Raytracer::Fragment(new_fragment(device, FRAGMENT_SOURCE, &layout, target_format, gbuffer)),
+        RenderBackend::Compute => Raytracer::Compute(new_compute(device, COMPUTE_SOURCE, &layout, gbuffer))
+    }
+}
+
+pub fn source_paths(dir: &Path, backend: RenderBackend) -> Vec<PathBuf> {
+    let files = match backend {
+        RenderBackend::Fragment => FRAGMENT_FILES,
+        RenderBackend::Compute => COMPUTE_FILES
+    };
+    files.iter().map(|file| dir.join(file)).collect()
+}
+
+/// Builds the raytracer from the WGSL files in `dir`, returning any naga or validation error instead of
+/// replacing a working pipeline.
+pub fn reload(
+    device: &wgpu::Device,
+    layouts: &Layouts,
+    backend: RenderBackend,
+    dir: &Path,
+    target_format: wgpu::TextureFormat,
+    gbuffer: bool
+) -> Result<Raytracer, String> {
+    let mut source = String::new();
+    for path in source_paths(dir, backend) {
+        source += &std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {path:?}: {e}"))?;
+    }
+    let layout = layouts.raytrace(device, backend, gbuffer);
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let raytracer = match backend {
+        RenderBackend::Fragment => Raytracer::Fragment(new_fragment(device, &source, &layout, target_format, gbuffer)),
+        RenderBackend::Compute => Raytracer::Compute(new_compute(device, &source, &layout, gbuffer))
+    };
+    match block_on(device.pop_error_scope()) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(raytracer)
+    }
+}
+
+fn new_fragment(
+    device: &wgpu::Device,
+    source: &str,
+    layout: &wgpu::PipelineLayout,
+    target_format: wgpu::TextureFormat,
+    gbuffer: bool
+) -> wgpu::RenderPipeline {
     let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some("RayTraceShader"),
-        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl")))
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source))
     });
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("RayTraceShader pipeline"),
-        layout: None,
+        layout: Some(layout),
         vertex: wgpu::VertexState {
             module: &shader,
             entry_point: "vs_main",
@@ -17,12 +93,17 @@ pub fn new(device: &wgpu::Device, surface_format: wgpu::TextureFormat) -> wgpu::
         },
         fragment: Some(wgpu::FragmentState {
             module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: surface_format,
-                blend: None,
-                write_mask: wgpu::ColorWrites::COLOR
-            })]
+            entry_point: if gbuffer { "fs_gbuffer" } else { "fs_main" },
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::COLOR
+                }),
+                Some(DEPTH_FORMAT.into()),
+                Some(NORMAL_FORMAT.into()),
+                Some(MATERIAL_FORMAT.into())
+            ][..if gbuffer { 4 } else { 1 }]
         }),
         primitive: wgpu::PrimitiveState {
             topology: wgpu::PrimitiveTopology::TriangleList,
@@ -43,36 +124,110 @@ pub fn new(device: &wgpu::Device, surface_format: wgpu::TextureFormat) -> wgpu::
     })
 }
 
+fn new_compute(device: &wgpu::Device, source: &str, layout: &wgpu::PipelineLayout, gbuffer: bool) -> wgpu::ComputePipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("RayTraceComputeShader"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source))
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("RayTraceComputeShader pipeline"),
+        layout: Some(layout),
+        module: &shader,
+        entry_point: if gbuffer { "cs_gbuffer" } else { "cs_main" }
+    })
+}
+
 pub fn draw(c: &Context) {
+    let Some((encoder, output_texture)) = c.profiler.time("cpu draw", || encode(c)) else { return };
+    c.profiler.time("cpu submit", || {
+        c.queue.submit(std::iter::once(encoder.finish()));
+        output_texture.present();
+    });
+    c.profiler.after_submit();
+}
+
+// Records the passes of a frame, or nothing when the surface has no texture to draw to
+fn encode(c: &Context) -> Option<(wgpu::CommandEncoder, wgpu::SurfaceTexture)> {
     let mut encoder = c.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
     let output_texture = match c.surface.get_current_texture() {
         Ok(v) => v,
-        Err(wgpu::SurfaceError::Lost) | Err(wgpu::SurfaceError::Outdated) => return c.resize(c.window.inner_size()),
-        Err(e) => panic!("Error getting current surface texture: {}", e)
+        Err(wgpu::SurfaceError::Lost) | Err(wgpu::SurfaceError::Outdated) => {
+            c.resize(c.window.inner_size());
+            return None
+        }
+        Err(wgpu::SurfaceError::Timeout) => {
+            log::warn!("Timed out getting the next surface texture, skipping the frame");
+            return None
+        }
+        Err(wgpu::SurfaceError::OutOfMemory) => {
+            log::error!("Out of memory getting the next surface texture");
+            c.device_lost.store(true, std::sync::atomic::Ordering::Relaxed);
+            return None
+        }
     };
     let view = output_texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
     let chunks_bind_group = &c.chunks.bind_group.lock().unwrap();
+    c.profiler.begin_gpu(&mut encoder);
 
-    {
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: true
-                }
-            })],
-            depth_stencil_attachment: None
-        });
-        render_pass.set_pipeline(&c.shader);
-        render_pass.set_bind_group(0, &c.camera.bind_group, &[]);
-        render_pass.set_bind_group(1, chunks_bind_group, &[]);
-        render_pass.draw(0..6, 0..1);
+    match &*c.shader.lock().unwrap() {
+        Raytracer::Fragment(pipeline) => {
+            let target_view = &c.render_target.view.lock().unwrap();
+            let gbuffer = c.render_target.gbuffer.lock().unwrap();
+            let mut views = vec![&**target_view];
+            if let Some(gbuffer) = gbuffer.as_ref() {
+                views.extend([&gbuffer.depth.view, &gbuffer.normal.view, &gbuffer.material.view]);
+            }
+            trace_fragment(&mut encoder, pipeline, &c.camera.bind_group, chunks_bind_group, views);
+        }
+        Raytracer::Compute(pipeline) => {
+            let size = *c.render_target.size.lock().unwrap();
+            let storage_bind_group = c.render_target.storage_bind_group.lock().unwrap();
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &c.camera.bind_group, &[]);
+            compute_pass.set_bind_group(1, chunks_bind_group, &[]);
+            compute_pass.set_bind_group(2, storage_bind_group.as_ref().unwrap(), &[]);
+            compute_pass.dispatch_workgroups(
+                size.width.div_ceil(TILE_SIZE),
+                size.height.div_ceil(TILE_SIZE),
+                1
+            );
+        }
     }
+    c.profiler.gpu_span(&mut encoder, "gpu trace");
+    upscale::draw(&mut encoder, &c.upscale, &c.render_target.bind_group.lock().unwrap(), &view);
+    c.profiler.gpu_span(&mut encoder, "gpu upscale");
+    c.profiler.draw_overlay(&c.ui, c.settings.lock().unwrap().target_frame_time);
+    let surface_size = c.window.inner_size();
+    c.ui.draw(&mut encoder, &c.queue, &view, surface_size);
+    c.profiler.gpu_span(&mut encoder, "gpu overlay");
+    c.profiler.resolve(&mut encoder);
+    Some((encoder, output_texture))
+}
 
-    c.queue.submit(std::iter::once(encoder.finish()));
-    output_texture.present();
-}
\ No newline at end of file
+pub fn trace_fragment(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    camera_bind_group: &wgpu::BindGroup,
+    chunks_bind_group: &wgpu::BindGroup,
+    views: Vec<&wgpu::TextureView>
+) {
+    let color_attachments: Vec<_> = views.into_iter().map(|view| Some(wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            store: true
+        }
+    })).collect();
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &color_attachments,
+        depth_stencil_attachment: None
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, camera_bind_group, &[]);
+    render_pass.set_bind_group(1, chunks_bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
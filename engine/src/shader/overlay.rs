@@ -0,0 +1,73 @@
+use std::borrow::Cow;
+
+use crate::Layouts;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct QuadInstance {
+    // Clip space corners
+    pub min: [f32;2],
+    pub max: [f32;2],
+    pub uv_min: [f32;2],
+    pub uv_max: [f32;2],
+    pub colour: [f32;4]
+}
+
+pub fn new(device: &wgpu::Device, layouts: &Layouts, surface_format: wgpu::TextureFormat, linear_output: bool) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("OverlayShader"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(concat!(include_str!("colour.wgsl"), include_str!("overlay.wgsl"))))
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("OverlayShader pipeline"),
+        layout: Some(&layouts.overlay(device)),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<QuadInstance>() as u64,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x2, 3 => Float32x2, 4 => Float32x4]
+            }]
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: if linear_output { "fs_linear" } else { "fs_main" },
+            targets: &[Some(wgpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL
+            })]
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None
+    })
+}
+
+pub fn draw(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    instances: &wgpu::Buffer,
+    count: u32,
+    view: &wgpu::TextureView
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Overlay pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: true
+            }
+        })],
+        depth_stencil_attachment: None
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.set_vertex_buffer(0, instances.slice(..));
+    render_pass.draw(0..6, 0..count);
+}
@@ -0,0 +1,35 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) colour: vec4<f32>
+};
+@vertex fn vs_main(
+    @builtin(vertex_index) i: u32,
+    @location(0) min: vec2<f32>,
+    @location(1) max: vec2<f32>,
+    @location(2) uv_min: vec2<f32>,
+    @location(3) uv_max: vec2<f32>,
+    @location(4) colour: vec4<f32>
+) -> VertexOutput {
+    // Two triangles covering the quad, in clip space
+    let corner = vec2<f32>(f32(i == 1u || i == 2u || i == 4u), f32(i >= 2u && i != 3u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(mix(min, max, corner), 0., 1.);
+    out.uv = mix(uv_min, uv_max, corner);
+    out.colour = colour;
+    return out;
+}
+
+@group(0) @binding(0)
+var atlas: texture_2d<f32>;
+@group(0) @binding(1)
+var atlas_sampler: sampler;
+
+@fragment fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.colour * textureSample(atlas, atlas_sampler, in.uv);
+}
+
+// For surfaces that expect linear colours
+@fragment fn fs_linear(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(srgb_to_linear(in.colour.rgb), in.colour.a) * textureSample(atlas, atlas_sampler, in.uv);
+}
@@ -1,56 +1,26 @@
 @vertex fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
-    switch i32(i) {
-        case 0:  { return vec4<f32>(-1.,  1., 0., 1.); }
-        case 1:  { return vec4<f32>(-1., -1., 0., 1.); }
-        case 2:  { return vec4<f32>( 1., -1., 0., 1.); }
-        case 3:  { return vec4<f32>( 1.,  1., 0., 1.); }
-        case 4:  { return vec4<f32>(-1.,  1., 0., 1.); }
-        default: { return vec4<f32>( 1., -1., 0., 1.); }
-    }
+    let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    return vec4<f32>(uv * 2. - 1., 0., 1.);
 }
 
-struct Camera {
-    @location(0) position: vec4<f32>,
-    @location(1) centre: vec4<f32>,
-    @location(2) u: vec4<f32>,
-    @location(3) v: vec4<f32>
-};
-@group(0) @binding(0)
-var<uniform> camera: Camera;
-
-struct Chunk {
-    @location(0) position: vec4<f32>,
-    @location(1) data: array<array<array<u32, 16>, 16>, 16>
-};
-@group(1) @binding(0)
-var<storage, read> chunks: array<Chunk>;
-@group(1) @binding(1)
-var<uniform> chunks_length: vec4<u32>;
-
-struct Ray {
-    a: vec3<f32>,
-    b: vec3<f32>,
-    ab: vec3<f32>
-}
-fn get_ray_from(a: vec3<f32>, b: vec3<f32>) -> Ray {
-    var ray: Ray;
-    ray.a = a;
-    ray.b = b;
-    ray.ab = b - a;
-    return ray;
+@fragment fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let dir = ray_direction(camera_generate_ray(screen_coord(position.xy)));
+    return shade(trace(camera.position.xyz, dir), dir);
 }
 
-fn camera_generate_ray(screen_coord: vec2<f32>) -> Ray {
-    let world_position = camera.centre.xyz + (camera.u.xyz * screen_coord.x) + (camera.v.xyz * screen_coord.y);
-    return get_ray_from(camera.position.xyz, world_position);
+struct GBufferOutput {
+    @location(0) colour: vec4<f32>,
+    @location(1) depth: f32,
+    @location(2) normal: vec4<f32>,
+    @location(3) material: u32
+};
+@fragment fn fs_gbuffer(@builtin(position) position: vec4<f32>) -> GBufferOutput {
+    let dir = ray_direction(camera_generate_ray(screen_coord(position.xy)));
+    let hit = trace(camera.position.xyz, dir);
+    var out: GBufferOutput;
+    out.colour = shade(hit, dir);
+    out.depth = linear_depth(hit, dir);
+    out.normal = vec4<f32>(hit.normal, f32(hit.material != 0u));
+    out.material = hit.material;
+    return out;
 }
-
-@fragment fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
-    let ray = camera_generate_ray(position.xy);
-    var chunk_id = u32(0);
-    while(chunk_id < chunks_length.x) {
-        let chunk = chunks[chunk_id];
-        chunk_id++;
-    }
-    return vec4<f32>(1.);
-}
\ No newline at end of file
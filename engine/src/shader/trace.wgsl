@@ -0,0 +1,271 @@
+struct Camera {
+    @location(0) position: vec4<f32>,
+    @location(1) centre: vec4<f32>,
+    @location(2) u: vec4<f32>,
+    @location(3) v: vec4<f32>,
+    @location(4) screen: vec4<f32>
+};
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct Debug {
+    mode: u32,
+    max_steps: u32,
+    depth_range: f32,
+    _padding: u32
+};
+@group(0) @binding(1)
+var<uniform> debug: Debug;
+
+struct Chunk {
+    @location(0) position: vec4<f32>,
+    @location(1) data: array<array<array<u32, 16>, 16>, 16>,
+    @location(2) lod1: array<array<array<u32, 8>, 8>, 8>,
+    @location(3) lod2: array<array<array<u32, 4>, 4>, 4>,
+    @location(4) lod3: array<array<array<u32, 2>, 2>, 2>
+};
+@group(1) @binding(0)
+var<storage, read> chunks: array<Chunk>;
+@group(1) @binding(1)
+var<uniform> chunks_length: vec4<u32>;
+@group(1) @binding(2)
+var<storage, read> octree: array<vec2<u32>>;
+@group(1) @binding(3)
+var<uniform> octree_info: vec4<f32>;
+@group(1) @binding(4)
+var<storage, read> materials: array<u32>;
+
+struct Ray {
+    a: vec3<f32>,
+    b: vec3<f32>,
+    ab: vec3<f32>
+}
+fn get_ray_from(a: vec3<f32>, b: vec3<f32>) -> Ray {
+    var ray: Ray;
+    ray.a = a;
+    ray.b = b;
+    ray.ab = b - a;
+    return ray;
+}
+
+fn screen_coord(pixel: vec2<f32>) -> vec2<f32> {
+    let coord = (pixel * camera.screen.zw) * 2. - 1.;
+    return vec2<f32>(coord.x, -coord.y);
+}
+
+fn camera_generate_ray(screen_coord: vec2<f32>) -> Ray {
+    let world_position = camera.centre.xyz + (camera.u.xyz * screen_coord.x) + (camera.v.xyz * screen_coord.y);
+    return get_ray_from(camera.position.xyz, world_position);
+}
+
+fn ray_direction(ray: Ray) -> vec3<f32> {
+    let dir = normalize(ray.ab);
+    return select(dir, vec3<f32>(0.000001), abs(dir) < vec3<f32>(0.000001));
+}
+
+struct Hit {
+    distance: f32,
+    normal: vec3<f32>,
+    material: u32,
+    chunk: u32,
+    steps: u32
+};
+fn no_hit() -> Hit {
+    var hit: Hit;
+    hit.distance = 1e30;
+    hit.normal = vec3<f32>(0.);
+    hit.material = 0u;
+    hit.chunk = 0xffffffffu;
+    hit.steps = 0u;
+    return hit;
+}
+
+fn chunk_voxel(chunk_id: u32, level: u32, cell: vec3<i32>) -> u32 {
+    switch level {
+        case 1u: { return chunks[chunk_id].lod1[cell.x][cell.y][cell.z]; }
+        case 2u: { return chunks[chunk_id].lod2[cell.x][cell.y][cell.z]; }
+        case 3u: { return chunks[chunk_id].lod3[cell.x][cell.y][cell.z]; }
+        default: { return chunks[chunk_id].data[cell.x][cell.y][cell.z]; }
+    }
+}
+
+// Level 0 is the full 16^3 grid, each further level halves the resolution and starts at twice the distance
+fn chunk_lod(distance: f32) -> u32 {
+    let lod_distance = f32(chunks_length.y);
+    if lod_distance <= 0. || distance < lod_distance { return 0u; }
+    return min(u32(log2(distance / lod_distance)) + 1u, 3u);
+}
+
+fn trace_chunk(chunk_id: u32, origin: vec3<f32>, dir: vec3<f32>, hit: ptr<function, Hit>) {
+    let lo = chunks[chunk_id].position.xyz;
+    let inv_dir = 1. / dir;
+    let t_lo = (lo - origin) * inv_dir;
+    let t_hi = (lo + 16. - origin) * inv_dir;
+    let t_min = min(t_lo, t_hi);
+    let t_max = max(t_lo, t_hi);
+    let t_enter = max(max(t_min.x, t_min.y), t_min.z);
+    let t_exit = min(min(t_max.x, t_max.y), t_max.z);
+    if t_enter > t_exit || t_exit < 0. || t_enter >= (*hit).distance { return; }
+
+    var t = max(t_enter, 0.);
+    let level = chunk_lod(t);
+    let cell_size = f32(1u << level);
+    let last_cell = (16 >> level) - 1;
+    let local = (origin + dir * t - lo) / cell_size;
+    var cell = clamp(vec3<i32>(floor(local)), vec3<i32>(0), vec3<i32>(last_cell));
+    let step = vec3<i32>(sign(dir));
+    let delta = abs(inv_dir) * cell_size;
+    var side = (vec3<f32>(cell) + select(vec3<f32>(0.), vec3<f32>(1.), dir > vec3<f32>(0.)) - local) * inv_dir * cell_size + t;
+    var normal = -vec3<f32>(step) * vec3<f32>(t_min == vec3<f32>(t_enter));
+
+    loop {
+        (*hit).steps++;
+        let material = chunk_voxel(chunk_id, level, cell);
+        if material != 0u {
+            (*hit).distance = t;
+            (*hit).normal = normal;
+            (*hit).material = material;
+            (*hit).chunk = chunk_id;
+            return;
+        }
+        if side.x < side.y && side.x < side.z {
+            t = side.x;
+            side.x += delta.x;
+            cell.x += step.x;
+            normal = vec3<f32>(-f32(step.x), 0., 0.);
+        } else if side.y < side.z {
+            t = side.y;
+            side.y += delta.y;
+            cell.y += step.y;
+            normal = vec3<f32>(0., -f32(step.y), 0.);
+        } else {
+            t = side.z;
+            side.z += delta.z;
+            cell.z += step.z;
+            normal = vec3<f32>(0., 0., -f32(step.z));
+        }
+        if any(cell < vec3<i32>(0)) || any(cell > vec3<i32>(last_cell)) || t >= (*hit).distance { return; }
+    }
+}
+
+// Steps through the empty cells of the octree by locating the current point from the root each time
+fn trace_octree(origin: vec3<f32>, dir: vec3<f32>, hit: ptr<function, Hit>) {
+    let size = octree_info.w;
+    if size <= 0. { return; }
+    let lo = octree_info.xyz;
+    let inv_dir = 1. / dir;
+    let t_lo = (lo - origin) * inv_dir;
+    let t_hi = (lo + size - origin) * inv_dir;
+    let t_min = min(t_lo, t_hi);
+    let t_max = max(t_lo, t_hi);
+    let t_enter = max(max(t_min.x, t_min.y), t_min.z);
+    let t_exit = min(min(t_max.x, t_max.y), t_max.z);
+    if t_enter > t_exit || t_exit < 0. || t_enter >= (*hit).distance { return; }
+
+    var t = max(t_enter, 0.);
+    var normal = -sign(dir) * vec3<f32>(t_min == vec3<f32>(t_enter));
+    for (var i = 0u; i < 512u; i++) {
+        if t >= t_exit || t >= (*hit).distance { return; }
+        (*hit).steps++;
+        let p = origin + dir * (t + 0.001) - lo;
+        var index = 0u;
+        var node_min = vec3<f32>(0.);
+        var node_size = size;
+        var material = 0u;
+        loop {
+            let node = octree[index];
+            if (node.x & 0x80000000u) != 0u {
+                material = node.x & 0x7fffffffu;
+                break;
+            }
+            node_size *= 0.5;
+            let upper = p >= node_min + node_size;
+            let child = u32(upper.x) | (u32(upper.y) << 1u) | (u32(upper.z) << 2u);
+            node_min += select(vec3<f32>(0.), vec3<f32>(node_size), upper);
+            if (node.x & (1u << child)) == 0u { break; }
+            index = node.y + countOneBits(node.x & ((1u << child) - 1u));
+        }
+        if material != 0u {
+            (*hit).distance = t;
+            (*hit).normal = normal;
+            (*hit).material = material;
+            (*hit).chunk = 0xfffffffeu;
+            return;
+        }
+        let cell_exit = (node_min + select(vec3<f32>(0.), vec3<f32>(node_size), dir > vec3<f32>(0.)) + lo - origin) * inv_dir;
+        let t_next = min(min(cell_exit.x, cell_exit.y), cell_exit.z);
+        normal = -sign(dir) * vec3<f32>(cell_exit == vec3<f32>(t_next));
+        t = max(t_next, t);
+    }
+}
+
+fn trace(origin: vec3<f32>, dir: vec3<f32>) -> Hit {
+    var hit = no_hit();
+    var chunk_id = u32(0);
+    while(chunk_id < chunks_length.x) {
+        trace_chunk(chunk_id, origin, dir, &hit);
+        chunk_id++;
+    }
+    trace_octree(origin, dir, &hit);
+    return hit;
+}
+
+fn linear_depth(hit: Hit, dir: vec3<f32>) -> f32 {
+    return hit.distance * dot(dir, normalize(camera.centre.xyz - camera.position.xyz));
+}
+
+fn id_color(id: u32) -> vec3<f32> {
+    let h = id * 2654435761u;
+    return vec3<f32>(f32(h & 255u), f32((h >> 8u) & 255u), f32((h >> 16u) & 255u)) / 255.;
+}
+
+fn material_color(material: u32) -> vec3<f32> {
+    if material < arrayLength(&materials) && materials[material] != 0u {
+        return unpack4x8unorm(materials[material]).rgb;
+    }
+    return id_color(material);
+}
+
+fn heatmap(value: f32) -> vec3<f32> {
+    let x = clamp(value, 0., 1.) * 4.;
+    return clamp(vec3<f32>(1.5) - abs(vec3<f32>(x) - vec3<f32>(3., 2., 1.)), vec3<f32>(0.), vec3<f32>(1.));
+}
+
+fn debug_shade(hit: Hit, dir: vec3<f32>, colour: vec4<f32>) -> vec4<f32> {
+    switch debug.mode {
+        case 1u: { return vec4<f32>(heatmap(f32(hit.steps) / f32(debug.max_steps)), 1.); }
+        case 2u: {
+            if hit.material == 0u || hit.chunk >= chunks_length.x { return colour; }
+            let local = (camera.position.xyz + dir * hit.distance) - chunks[hit.chunk].position.xyz;
+            let edge = vec3<f32>(min(local, 16. - local) < vec3<f32>(0.15));
+            if edge.x + edge.y + edge.z >= 2. { return vec4<f32>(1., 0.2, 0.1, 1.); }
+            return colour;
+        }
+        case 3u: { return vec4<f32>(hit.normal * 0.5 + 0.5, 1.) * f32(hit.material != 0u); }
+        case 4u: {
+            if hit.material == 0u { return vec4<f32>(0., 0., 0., 1.); }
+            return vec4<f32>(vec3<f32>(1. - clamp(linear_depth(hit, dir) / debug.depth_range, 0., 1.)), 1.);
+        }
+        case 5u: { return vec4<f32>(id_color(hit.material), 1.); }
+        case 6u: {
+            if hit.material == 0u { return vec4<f32>(0., 0., 0., 1.); }
+            return vec4<f32>(id_color(hit.chunk + 1u), 1.);
+        }
+        default: { return colour; }
+    }
+}
+
+fn surface_shade(hit: Hit, dir: vec3<f32>) -> vec4<f32> {
+    if hit.material == 0u {
+        return vec4<f32>(mix(vec3<f32>(0.8, 0.85, 0.9), vec3<f32>(0.35, 0.55, 0.9), clamp(dir.z, 0., 1.)), 1.);
+    }
+    let light = max(dot(hit.normal, normalize(vec3<f32>(0.4, -0.3, 0.85))), 0.) * 0.75 + 0.25;
+    return vec4<f32>(material_color(hit.material) * light, 1.);
+}
+
+fn shade(hit: Hit, dir: vec3<f32>) -> vec4<f32> {
+    if debug.mode != 0u {
+        return debug_shade(hit, dir, surface_shade(hit, dir));
+    }
+    return surface_shade(hit, dir);
+}
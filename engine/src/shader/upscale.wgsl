@@ -0,0 +1,48 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>
+};
+@vertex fn vs_main(@builtin(vertex_index) i: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2. - 1., 1. - uv.y * 2., 0., 1.);
+    out.uv = uv;
+    return out;
+}
+
+// x: sharpness, y: 1 when the surface expects linear colours
+struct Upscale {
+    sharpness: vec4<f32>
+};
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> upscale: Upscale;
+
+fn output(c: vec4<f32>) -> vec4<f32> {
+    if upscale.sharpness.y > 0. {
+        return vec4<f32>(srgb_to_linear(c.rgb), c.a);
+    }
+    return c;
+}
+
+@fragment fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1. / vec2<f32>(textureDimensions(source));
+    let c = textureSample(source, source_sampler, in.uv);
+    let n = textureSample(source, source_sampler, in.uv - vec2<f32>(0., texel.y)).rgb;
+    let s = textureSample(source, source_sampler, in.uv + vec2<f32>(0., texel.y)).rgb;
+    let e = textureSample(source, source_sampler, in.uv + vec2<f32>(texel.x, 0.)).rgb;
+    let w = textureSample(source, source_sampler, in.uv - vec2<f32>(texel.x, 0.)).rgb;
+    if upscale.sharpness.x <= 0. {
+        return output(c);
+    }
+    // Contrast adaptive sharpening over the cross neighbourhood, as in FSR's RCAS pass
+    let lo = min(c.rgb, min(min(n, s), min(e, w)));
+    let hi = max(c.rgb, max(max(n, s), max(e, w)));
+    let amp = sqrt(clamp(min(lo, 1. - hi) / max(hi, vec3<f32>(0.0001)), vec3<f32>(0.), vec3<f32>(1.)));
+    let weight = amp * (-1. / mix(8., 5., clamp(upscale.sharpness.x, 0., 1.)));
+    let colour = (c.rgb + (n + s + e + w) * weight) / (1. + 4. * weight);
+    return output(vec4<f32>(clamp(colour, vec3<f32>(0.), vec3<f32>(1.)), c.a));
+}
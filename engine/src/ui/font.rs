@@ -0,0 +1,50 @@
+use image::RgbaImage;
+
+// Each glyph is 5x7 pixels in a 6x8 cell, the extra row and column spacing characters and lines apart
+pub const GLYPH_CELL: [u32;2] = [6, 8];
+const COLUMNS: u32 = 16;
+const FIRST: char = ' ';
+const LAST: char = '~';
+// The cell after the last glyph is solid, for drawing flat rectangles with the same pipeline
+const SOLID: u32 = LAST as u32 - FIRST as u32 + 1;
+
+/// The printable ASCII characters in a white on transparent atlas, 16 cells per row starting at the space.
+pub struct Font {
+    pub image: RgbaImage
+}
+impl Font {
+    pub fn load() -> Self {
+        let image = image::load_from_memory(include_bytes!("font.png")).expect("Invalid font atlas").to_rgba8();
+        Self { image }
+    }
+    /// Top left pixel of the cell of `c`, characters outside the atlas show as '?'.
+    pub fn cell(c: char) -> [u32;2] {
+        let c = if (FIRST..=LAST).contains(&c) { c } else { '?' };
+        cell_origin(c as u32 - FIRST as u32)
+    }
+    /// Texture coordinates of the cell of `c`.
+    pub fn uv(&self, c: char) -> ([f32;2], [f32;2]) {
+        self.cell_uv(Self::cell(c))
+    }
+    /// Texture coordinates inside the solid cell.
+    pub fn solid_uv(&self) -> ([f32;2], [f32;2]) {
+        let [x, y] = cell_origin(SOLID);
+        // A single point in the middle, so filtering never reaches the neighbouring cells
+        let uv = [
+            (x + GLYPH_CELL[0] / 2) as f32 / self.image.width() as f32,
+            (y + GLYPH_CELL[1] / 2) as f32 / self.image.height() as f32
+        ];
+        (uv, uv)
+    }
+    fn cell_uv(&self, [x, y]: [u32;2]) -> ([f32;2], [f32;2]) {
+        let size = [self.image.width() as f32, self.image.height() as f32];
+        (
+            [x as f32 / size[0], y as f32 / size[1]],
+            [(x + GLYPH_CELL[0]) as f32 / size[0], (y + GLYPH_CELL[1]) as f32 / size[1]]
+        )
+    }
+}
+
+fn cell_origin(index: u32) -> [u32;2] {
+    [index % COLUMNS * GLYPH_CELL[0], index / COLUMNS * GLYPH_CELL[1]]
+}
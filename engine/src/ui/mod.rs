@@ -0,0 +1,139 @@
+use std::{sync::{Mutex, atomic::AtomicBool}, time::Instant};
+
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::{shader::overlay::{self, QuadInstance}, Layouts};
+
+mod font; pub use font::*;
+
+pub const MAX_QUADS: usize = 4096;
+pub const WHITE: [f32;4] = [1., 1., 1., 1.];
+
+// A quad in window pixels from the top left corner, with an sRGB colour
+#[derive(Copy, Clone, Debug)]
+struct Quad {
+    min: [f32;2],
+    max: [f32;2],
+    uv: ([f32;2], [f32;2]),
+    colour: [f32;4]
+}
+
+/// Immediate mode text and rectangles drawn over the frame. Anything added during a frame is drawn in one
+/// batch after the upscale pass and then cleared.
+pub struct Ui {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    buffer: wgpu::Buffer,
+    font: Font,
+    quads: Mutex<Vec<Quad>>,
+    // Pixels per font pixel
+    pub scale: f32,
+    // Whether the frame rate, position and crosshair are shown
+    pub hud: AtomicBool,
+    frame_time: Mutex<(Instant, f32)>
+}
+impl Ui {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layouts: &Layouts,
+        surface_format: wgpu::TextureFormat,
+        linear_output: bool
+    ) -> Self {
+        let font = Font::load();
+        let texture = device.create_texture_with_data(queue, &wgpu::TextureDescriptor {
+            label: Some("Font atlas"),
+            size: wgpu::Extent3d { width: font.image.width(), height: font.image.height(), depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+        }, &font.image);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Font sampler"),
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layouts.overlay,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view)
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler)
+                }
+            ]
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Overlay quads"),
+            size: (MAX_QUADS * std::mem::size_of::<QuadInstance>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+        Self {
+            pipeline: overlay::new(device, layouts, surface_format, linear_output),
+            bind_group,
+            buffer,
+            font,
+            quads: Mutex::new(vec![]),
+            scale: 2.,
+            hud: AtomicBool::new(true),
+            frame_time: Mutex::new((Instant::now(), 0.))
+        }
+    }
+    pub fn rect(&self, min: [f32;2], max: [f32;2], colour: [f32;4]) {
+        self.quads.lock().unwrap().push(Quad { min, max, uv: self.font.solid_uv(), colour });
+    }
+    /// White text with its top left corner at `position`, returns its size.
+    pub fn text(&self, position: [f32;2], text: &str) -> [f32;2] {
+        self.text_coloured(position, WHITE, text)
+    }
+    pub fn text_coloured(&self, position: [f32;2], colour: [f32;4], text: &str) -> [f32;2] {
+        let [width, height] = GLYPH_CELL.map(|v| v as f32 * self.scale);
+        let mut quads = self.quads.lock().unwrap();
+        for (row, line) in text.lines().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                if c.is_whitespace() { continue }
+                let min = [position[0] + column as f32 * width, position[1] + row as f32 * height];
+                quads.push(Quad { min, max: [min[0] + width, min[1] + height], uv: self.font.uv(c), colour });
+            }
+        }
+        text_size(text, self.scale)
+    }
+    /// Smoothed time between the last frames in milliseconds.
+    pub fn frame_time(&self) -> f32 {
+        self.frame_time.lock().unwrap().1
+    }
+    /// Draws and clears everything added since the last frame.
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, view: &wgpu::TextureView, size: PhysicalSize<u32>) {
+        let mut frame_time = self.frame_time.lock().unwrap();
+        let now = Instant::now();
+        frame_time.1 += ((now - frame_time.0).as_secs_f32() * 1000. - frame_time.1) * 0.05;
+        frame_time.0 = now;
+
+        let mut quads = self.quads.lock().unwrap();
+        if quads.is_empty() { return }
+        if quads.len() > MAX_QUADS {
+            log::warn!("Dropping {} overlay quads over the limit of {MAX_QUADS}", quads.len() - MAX_QUADS);
+        }
+        let to_clip = |[x, y]: [f32;2]| [x / size.width as f32 * 2. - 1., 1. - y / size.height as f32 * 2.];
+        let instances: Vec<QuadInstance> = quads.drain(..).take(MAX_QUADS).map(|quad| {
+            QuadInstance { min: to_clip(quad.min), max: to_clip(quad.max), uv_min: quad.uv.0, uv_max: quad.uv.1, colour: quad.colour }
+        }).collect();
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&instances));
+        overlay::draw(encoder, &self.pipeline, &self.bind_group, &self.buffer, instances.len() as u32, view);
+    }
+}
+
+/// Size in pixels of `text` drawn at `scale`, one line per line break.
+pub fn text_size(text: &str, scale: f32) -> [f32;2] {
+    let columns = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+    let rows = text.lines().count();
+    [columns as f32 * GLYPH_CELL[0] as f32 * scale, rows as f32 * GLYPH_CELL[1] as f32 * scale]
+}
@@ -0,0 +1,86 @@
+use futures::executor::block_on;
+
+use crate::{EngineError, GraphicsBackend, Settings};
+
+pub const REQUIRED_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+// Requested when the adapter has them
+pub const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::TIMESTAMP_QUERY;
+
+pub fn backends(settings: &Settings) -> wgpu::Backends {
+    match settings.graphics_backend {
+        GraphicsBackend::Auto => wgpu::Backends::all(),
+        GraphicsBackend::Vulkan => wgpu::Backends::VULKAN,
+        GraphicsBackend::Metal => wgpu::Backends::METAL,
+        GraphicsBackend::Dx12 => wgpu::Backends::DX12,
+        GraphicsBackend::Dx11 => wgpu::Backends::DX11,
+        GraphicsBackend::Gl => wgpu::Backends::GL
+    }
+}
+
+pub fn adapters(settings: &Settings) -> Vec<wgpu::AdapterInfo> {
+    wgpu::Instance::new(backends(settings)).enumerate_adapters(backends(settings)).map(|a| a.get_info()).collect()
+}
+
+/// Ranks an adapter that meets the requirements, higher is better. Unusable adapters, and any other than
+/// software ones when the fallback adapter is forced, get `None`.
+pub fn score_adapter(info: &wgpu::AdapterInfo, usable: bool, settings: &Settings) -> Option<u32> {
+    if !usable { return None }
+    if settings.force_fallback_adapter && info.device_type != wgpu::DeviceType::Cpu { return None }
+    let mut score = match info.device_type {
+        wgpu::DeviceType::DiscreteGpu => 400,
+        wgpu::DeviceType::IntegratedGpu => 300,
+        wgpu::DeviceType::VirtualGpu => 200,
+        wgpu::DeviceType::Cpu => 100,
+        wgpu::DeviceType::Other => 0
+    };
+    if let Some(name) = &settings.adapter_name {
+        if info.name.to_lowercase().contains(&name.to_lowercase()) { score += 1000 }
+    }
+    Some(score)
+}
+
+/// Picks the best scoring adapter that can present to `surface`, or render offscreen without one.
+pub fn create_adapter(instance: &wgpu::Instance, surface: Option<&wgpu::Surface>, settings: &Settings) -> Result<wgpu::Adapter, EngineError> {
+    let required_limits = wgpu::Limits::default();
+    let mut best: Option<(u32, wgpu::Adapter)> = None;
+    for adapter in instance.enumerate_adapters(backends(settings)) {
+        let info = adapter.get_info();
+        let usable = surface.is_none_or(|surface| adapter.is_surface_supported(surface))
+            && required_limits.check_limits(&adapter.limits())
+            && adapter.features().contains(REQUIRED_FEATURES);
+        let score = score_adapter(&info, usable, settings);
+        log::info!("Found adapter {} ({:?}, {:?}): {}", info.name, info.backend, info.device_type,
+            score.map_or("unusable".to_string(), |score| format!("score {score}")));
+        if let Some(score) = score {
+            if best.as_ref().is_none_or(|(best, _)| score > *best) {
+                best = Some((score, adapter));
+            }
+        }
+    }
+    let (_, adapter) = best.ok_or(EngineError::NoAdapter)?;
+    let info = adapter.get_info();
+    if let Some(name) = &settings.adapter_name {
+        if !info.name.to_lowercase().contains(&name.to_lowercase()) {
+            log::warn!("No usable adapter matches \"{name}\"");
+        }
+    }
+    log::info!("Using adapter {} ({:?}, {:?}, driver {} {})", info.name, info.backend, info.device_type, info.driver, info.driver_info);
+    Ok(adapter)
+}
+
+pub fn create_device_queue(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), EngineError> {
+    block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            features: REQUIRED_FEATURES | (adapter.features() & OPTIONAL_FEATURES),
+            limits: wgpu::Limits::default(),
+            label: None
+        },
+        None
+    )).map_err(EngineError::RequestDevice)
+}
+
+/// Whether a wgpu error or panic message means the device was lost or ran out of memory, which only
+/// recreating the device recovers from.
+pub fn is_device_loss(message: &str) -> bool {
+    message.contains("parent device is lost") || message.contains("not enough memory left")
+}
@@ -0,0 +1,130 @@
+use std::{cell::Cell, fmt::Write, io, panic::{self, AssertUnwindSafe, PanicHookInfo}, path::{Path, PathBuf}, sync::{Arc, Mutex, PoisonError}};
+
+use chrono::Local;
+
+use crate::Settings;
+
+// Log lines included in a crash report
+const CRASH_LOG_LINES: usize = 100;
+
+lazy_static::lazy_static! {
+    static ref CRASH_CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext::default());
+    // Found on first use, which has to come before old logs are pruned
+    static ref PREVIOUS_CRASH: Option<PathBuf> = find_previous_crash();
+}
+
+thread_local! {
+    // Set while a panic from a lost device is caught to recover from it, so it isn't reported as a crash
+    static CATCHING_DEVICE_LOSS: Cell<bool> = const { Cell::new(false) };
+}
+
+#[derive(Default)]
+struct CrashContext {
+    adapter: Option<wgpu::AdapterInfo>,
+    settings: Option<Arc<Mutex<Settings>>>
+}
+
+/// Records the adapter and live settings that crash reports describe.
+pub fn set_crash_context(adapter: wgpu::AdapterInfo, settings: Arc<Mutex<Settings>>) {
+    *CRASH_CONTEXT.lock().unwrap_or_else(PoisonError::into_inner) = CrashContext { adapter: Some(adapter), settings: Some(settings) };
+}
+
+/// Writes a report of the panic to a timestamped file in the data directory and logs where it went.
+pub fn report_crash(panic_info: &PanicHookInfo) {
+    if CATCHING_DEVICE_LOSS.with(Cell::get) && super::is_device_loss(&panic_info.to_string()) { return }
+    let thread = std::thread::current().name().unwrap_or("unnamed").to_string();
+    let backtrace = format!("{:?}", backtrace::Backtrace::new());
+    let report = crash_report(&format!("{panic_info} (thread {thread})"), &backtrace);
+    match write_crash_report(&report) {
+        Ok(path) => {
+            log::error!("{panic_info}\nA crash report was written to {path:?}");
+            // The log may only be going to a file
+            eprintln!("A crash report was written to {path:?}");
+        }
+        Err(e) => log::error!("{panic_info}\nFailed to write a crash report: {e}\n{report}")
+    }
+}
+
+/// The text of a crash report for the panic `message`.
+pub fn crash_report(message: &str, backtrace: &str) -> String {
+    let context = CRASH_CONTEXT.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut report = String::new();
+    writeln!(report, "D32 {} crash report, {}", env!("CARGO_PKG_VERSION"), Local::now().format("%Y-%m-%d %H:%M:%S")).unwrap();
+    writeln!(report, "OS: {}", os_info()).unwrap();
+    match &context.adapter {
+        Some(adapter) => writeln!(report, "Adapter: {} ({:?}, {:?}, driver {} {})", adapter.name, adapter.backend,
+            adapter.device_type, adapter.driver, adapter.driver_info).unwrap(),
+        None => writeln!(report, "Adapter: none created").unwrap()
+    }
+    writeln!(report, "\nPanic: {message}").unwrap();
+
+    // The panicking thread may hold the lock
+    let settings = match context.settings.as_ref().map(|settings| settings.try_lock()) {
+        Some(Ok(settings)) => serde_json::to_string_pretty(&*settings).unwrap(),
+        Some(Err(_)) => "locked by a thread".into(),
+        None => "not loaded".into()
+    };
+    writeln!(report, "\nSettings: {settings}").unwrap();
+    writeln!(report, "\nBacktrace:\n{backtrace}").unwrap();
+    writeln!(report, "Log:").unwrap();
+    for (level, line) in super::recent_log(CRASH_LOG_LINES) {
+        writeln!(report, "{level:5} {line}").unwrap();
+    }
+    report
+}
+
+/// Runs `f`, returning the panic message instead of unwinding further when wgpu panics because the device
+/// was lost or ran out of memory, as it does in calls that can't return an error. Other panics carry on.
+pub fn catch_device_loss<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    CATCHING_DEVICE_LOSS.with(|catching| catching.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING_DEVICE_LOSS.with(|catching| catching.set(false));
+    result.or_else(|payload| {
+        let message = payload.downcast_ref::<String>().cloned()
+            .or_else(|| payload.downcast_ref::<&str>().map(|message| message.to_string()))
+            .unwrap_or_default();
+        if super::is_device_loss(&message) { Err(message) } else { panic::resume_unwind(payload) }
+    })
+}
+
+/// The crash report written during the previous session, if it crashed.
+pub fn previous_crash_report() -> Option<&'static Path> {
+    PREVIOUS_CRASH.as_deref()
+}
+
+/// The newest crash report in `dir` written at or after `since`, a timestamp like `20240102-100000`.
+pub fn crash_report_since(dir: &Path, since: &str) -> Option<PathBuf> {
+    std::fs::read_dir(dir).ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.file_name().and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("crash-")?.strip_suffix(".txt"))
+            .is_some_and(|stamp| stamp >= since))
+        .max()
+}
+
+fn find_previous_crash() -> Option<PathBuf> {
+    let log_path = super::log_path();
+    let current = log_path.file_name()?.to_str()?;
+    // The log of the previous session is the newest one before this session's
+    let previous = std::fs::read_dir(log_path.parent()?).ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.starts_with("trace-") && name.ends_with(".log") && name.as_str() < current)
+        .max()?;
+    crash_report_since(&super::data_dir(), previous.strip_prefix("trace-")?.strip_suffix(".log")?)
+}
+
+fn write_crash_report(report: &str) -> io::Result<PathBuf> {
+    let dir = super::data_dir();
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("crash-{}.txt", Local::now().format("%Y%m%d-%H%M%S")));
+    std::fs::write(&path, report)?;
+    Ok(path)
+}
+
+fn os_info() -> String {
+    // The distribution on Linux
+    let release = std::fs::read_to_string("/etc/os-release").ok().and_then(|release| release.lines()
+        .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+        .map(|name| format!(" ({})", name.trim_matches('"'))));
+    format!("{} {}{}", std::env::consts::OS, std::env::consts::ARCH, release.unwrap_or_default())
+}
@@ -0,0 +1,85 @@
+use std::{path::{Path, PathBuf}, sync::Mutex};
+
+use directories::{ProjectDirs, UserDirs};
+
+/// Overrides where everything is kept, as does `--data-dir` on the command line.
+pub const DATA_DIR_ENV: &str = "D32_DATA_DIR";
+/// A file with this name next to the executable keeps everything in a `data` directory beside it.
+pub const PORTABLE_MARKER: &str = "portable";
+
+lazy_static::lazy_static! {
+    static ref OVERRIDE: Mutex<Option<PathBuf>> = Mutex::new(None);
+    // Resolved on first use, which must not log since the log file lives here too
+    static ref DATA_DIRS: DataDirs = {
+        let override_dir = OVERRIDE.lock().unwrap().clone()
+            .or_else(|| std::env::var_os(DATA_DIR_ENV).filter(|v| !v.is_empty()).map(PathBuf::from));
+        let exe = std::env::current_exe().ok();
+        DataDirs::resolve(override_dir, exe.as_deref().and_then(Path::parent))
+    };
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DataDirSource {
+    Override,
+    Portable,
+    // The documents directory of earlier versions, kept while it has settings in it
+    Legacy,
+    Platform,
+    // Nothing else was available, the temporary directory is used
+    Fallback
+}
+
+/// Settings go in `config`; crash reports, profiles and G-buffer dumps in `data`; session logs in `cache`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DataDirs {
+    pub config: PathBuf,
+    pub data: PathBuf,
+    pub cache: PathBuf,
+    pub source: DataDirSource
+}
+impl DataDirs {
+    /// Picks an override first, then portable mode when `exe_dir` has the marker, then an existing legacy
+    /// directory, then the platform directories (XDG on Linux) and finally the temporary directory.
+    pub fn resolve(override_dir: Option<PathBuf>, exe_dir: Option<&Path>) -> Self {
+        if let Some(dir) = override_dir {
+            return Self::single(dir, DataDirSource::Override)
+        }
+        if let Some(dir) = exe_dir.filter(|dir| dir.join(PORTABLE_MARKER).exists()) {
+            return Self::single(dir.join("data"), DataDirSource::Portable)
+        }
+        let legacy = UserDirs::new().and_then(|dirs| Some(dirs.document_dir()?.join(env!("DOC_PATH"))));
+        if let Some(dir) = legacy.filter(|dir| dir.join("settings.json").exists()) {
+            return Self::single(dir, DataDirSource::Legacy)
+        }
+        match ProjectDirs::from("", "", "D32") {
+            Some(dirs) => Self {
+                config: dirs.config_dir().into(),
+                data: dirs.data_dir().into(),
+                cache: dirs.cache_dir().into(),
+                source: DataDirSource::Platform
+            },
+            None => Self::single(std::env::temp_dir().join("d32"), DataDirSource::Fallback)
+        }
+    }
+    fn single(dir: PathBuf, source: DataDirSource) -> Self {
+        Self { config: dir.clone(), data: dir.clone(), cache: dir.join("logs"), source }
+    }
+}
+
+/// Keeps everything in `dir`. Only takes effect before anything was logged or saved.
+pub fn set_data_dir(dir: PathBuf) {
+    *OVERRIDE.lock().unwrap() = Some(dir);
+}
+
+pub fn data_dirs() -> &'static DataDirs {
+    &DATA_DIRS
+}
+pub fn config_dir() -> PathBuf {
+    DATA_DIRS.config.clone()
+}
+pub fn data_dir() -> PathBuf {
+    DATA_DIRS.data.clone()
+}
+pub fn cache_dir() -> PathBuf {
+    DATA_DIRS.cache.clone()
+}
@@ -0,0 +1,38 @@
+use std::{path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Polls modification times, which is cheap enough to call every frame since the disk is only hit twice a second
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant
+}
+impl FileWatcher {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            files: paths.into_iter().map(|path| {
+                let modified = modified(&path);
+                (path, modified)
+            }).collect(),
+            last_poll: Instant::now()
+        }
+    }
+    /// Returns whether any of the files were modified, created or removed since the previous call.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL { return false }
+        self.last_poll = Instant::now();
+        let mut changed = false;
+        for (path, last_modified) in &mut self.files {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -1,38 +1,68 @@
-use std::{panic, io::Write, fs::File, sync::Mutex, env::current_dir};
+use std::{collections::VecDeque, panic, io::{self, Write}, fs::File, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, Ordering}, Mutex, PoisonError, RwLock}};
 use backtrace::SymbolName;
-use chrono::{Local, Timelike};
-use env_logger::{Builder, WriteStyle};
-use log::{LevelFilter, Level};
+use chrono::{Local, SecondsFormat, Timelike};
+use env_logger::{filter::Filter, Builder, WriteStyle};
+use log::{kv::{self, VisitSource}, LevelFilter, Level, Log, Metadata, Record};
+use serde_json::{Map, Value};
+
+use crate::Settings;
+
+// Everything from the engine, with the chattier graphics crates toned down
+pub const DEFAULT_LOG_FILTER: &str = "trace,wgpu_core=info,wgpu_core::device=warn,wgpu_hal=info,naga=info";
 
 lazy_static::lazy_static! {
-    static ref FILE: Mutex<File> = {
-        let path = directories::UserDirs::new().unwrap().document_dir().unwrap().join(env!("DOC_PATH")).join("trace.log");
-        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
-        Mutex::new(std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(path).unwrap())
-    };
+    // A new file per session, named after the time it started
+    static ref LOG_PATH: PathBuf = super::cache_dir().join(format!("trace-{}.log", Local::now().format("%Y%m%d-%H%M%S")));
+    static ref FILE: Mutex<Option<File>> = Mutex::new(open_log_file("log"));
+    // The same records as JSON lines for tools, opened once enabled
+    static ref JSON_FILE: Mutex<Option<File>> = Mutex::new(open_log_file("jsonl"));
+    static ref FILTER: RwLock<Filter> = RwLock::new(build_filter(DEFAULT_LOG_FILTER, std::env::var("RUST_LOG").ok().as_deref()));
+    static ref RECENT: Mutex<VecDeque<(Level, String)>> = Mutex::new(VecDeque::new());
+}
+
+// Lines kept for the console
+const RECENT_LINES: usize = 256;
+
+static JSON_LOG: AtomicBool = AtomicBool::new(false);
+
+// Applies the filter that can change while running before handing records to env_logger for formatting
+struct Logger {
+    inner: env_logger::Logger
+}
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        FILTER.read().unwrap_or_else(PoisonError::into_inner).enabled(metadata)
+    }
+    fn log(&self, record: &Record) {
+        if FILTER.read().unwrap_or_else(PoisonError::into_inner).matches(record) {
+            if JSON_LOG.load(Ordering::Relaxed) {
+                append(&JSON_FILE, &json_line(record));
+            }
+            self.inner.log(record)
+        }
+    }
+    fn flush(&self) {
+        self.inner.flush()
+    }
 }
 
 pub fn start_logger() {
-    let mut builder = Builder::new();
-    builder
+    let inner = Builder::new()
         .filter(None, LevelFilter::Trace)
-        .filter(Some("wgpu_core"), LevelFilter::Info)
-        .filter(Some("wgpu_core::device"), LevelFilter::Warn)
-        .filter(Some("wgpu_hal"), LevelFilter::Info)
-        .filter(Some("naga"), LevelFilter::Info)
         .format(|buf, record| {
             let level = record.level();
-            let args = record.args();
+            let args = format!("{}{}", record.args(), text_fields(record));
+            push_recent(level, &args);
             if level == Level::Trace {
                 append_log(format!("{args}\n"));
                 writeln!(buf, "\n\x1b[35m{args}\x1b[0m")
             } else {
                 let now = Local::now();
                 let timestamp = format!("{:02}:{:02}:{:02}", now.hour(), now.minute(), now.second());
-                let module = match record.module_path() { Some(v)=>v, None=>"" };
-                let line = match record.line() { Some(v)=>v, None=>0 };
+                let module = record.module_path().unwrap_or_default();
+                let line = record.line().unwrap_or_default();
                 let styled_level = buf.default_styled_level(level);
-                append_log(format!("{timestamp} {styled_level} {module}:{line} {args}\r\n"));
+                append_log(format!("{timestamp} {level} {module}:{line} {args}\n"));
                 if level == Level::Error {
                     writeln!(buf, "\x1b[90m{timestamp} {styled_level} \x1b[96m{module}:{line}\x1b[0m {args}\n{}",
                         get_backtrace())
@@ -42,25 +72,166 @@ pub fn start_logger() {
             }
         })
         .write_style(WriteStyle::Always)
-        .init();
-    panic::set_hook(Box::new(|panic_info| log::error!("{panic_info}")));
+        .build();
+    log::set_boxed_logger(Box::new(Logger { inner })).expect("The logger was already started");
+    log::set_max_level(FILTER.read().unwrap().filter());
+    panic::set_hook(Box::new(super::report_crash));
+}
+
+/// Applies the log filter and JSON output from the settings and removes session logs beyond the number kept.
+pub fn configure_logger(settings: &Settings) {
+    set_log_filter(&settings.log_filter);
+    set_json_log(settings.json_log);
+    log::info!("Logging to {:?}", *LOG_PATH);
+    let dirs = super::data_dirs();
+    if dirs.source == super::DataDirSource::Fallback {
+        log::warn!("No home directory found, keeping settings and logs in {:?}", dirs.data);
+    }
+    if let Some(report) = super::previous_crash_report() {
+        log::warn!("The previous session crashed, the report is in {report:?}");
+    }
+    let dir = LOG_PATH.parent().unwrap();
+    if let Err(e) = prune_logs(dir, settings.log_history.max(1) as usize) {
+        log::warn!("Failed to remove old logs from {dir:?}: {e}");
+    }
+}
+
+/// Uses `filter` in env_logger syntax, such as `info,engine=trace`, unless `RUST_LOG` is set.
+pub fn set_log_filter(filter: &str) {
+    let filter = build_filter(filter, std::env::var("RUST_LOG").ok().as_deref());
+    log::set_max_level(filter.filter());
+    *FILTER.write().unwrap_or_else(PoisonError::into_inner) = filter;
+}
+
+/// Parses `rust_log` when it is set and not empty, otherwise `filter`.
+pub fn build_filter(filter: &str, rust_log: Option<&str>) -> Filter {
+    let spec = rust_log.filter(|v| !v.trim().is_empty()).unwrap_or(filter);
+    env_logger::filter::Builder::new().parse(spec).build()
+}
+
+/// Whether records are also written to the JSON lines log next to the text log.
+pub fn set_json_log(enabled: bool) {
+    JSON_LOG.store(enabled, Ordering::Relaxed);
+}
+
+/// A record as one line of JSON with its time, level, module, line, message and key-value fields, as logged
+/// with `log::info!(chunk = 3; "Loaded")`.
+pub fn json_line(record: &Record) -> String {
+    struct Fields(Map<String, Value>);
+    impl<'kvs> VisitSource<'kvs> for Fields {
+        fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+            let value = if let Some(v) = value.to_bool() { v.into() }
+                else if let Some(v) = value.to_u64() { v.into() }
+                else if let Some(v) = value.to_i64() { v.into() }
+                else if let Some(v) = value.to_f64() { v.into() }
+                else { value.to_string().into() };
+            self.0.insert(key.to_string(), value);
+            Ok(())
+        }
+    }
+    let mut fields = Fields(Map::new());
+    record.key_values().visit(&mut fields).unwrap();
+    let line = serde_json::json!({
+        "time": Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+        "level": record.level().as_str(),
+        "module": record.module_path(),
+        "line": record.line(),
+        "message": record.args().to_string(),
+        "fields": fields.0
+    });
+    format!("{line}\n")
+}
+
+// Key-value fields appended to the text output as ` key=value`
+fn text_fields(record: &Record) -> String {
+    struct Fields(String);
+    impl<'kvs> VisitSource<'kvs> for Fields {
+        fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+            self.0.push_str(&format!(" {key}={value}"));
+            Ok(())
+        }
+    }
+    let mut fields = Fields(String::new());
+    record.key_values().visit(&mut fields).unwrap();
+    fields.0
+}
+
+/// The text log file of this session.
+pub fn log_path() -> &'static Path {
+    &LOG_PATH
+}
+
+/// Deletes all but the newest `keep` session logs of each format in `dir`.
+pub fn prune_logs(dir: &Path, keep: usize) -> io::Result<()> {
+    for extension in [".log", ".jsonl"] {
+        let mut logs: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("trace-") && name.ends_with(extension)))
+            .collect();
+        // The timestamps sort in the order the sessions started
+        logs.sort();
+        for path in logs.iter().rev().skip(keep) {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+fn open_log_file(extension: &str) -> Option<File> {
+    let path = LOG_PATH.with_extension(extension);
+    let file = std::fs::create_dir_all(path.parent().unwrap())
+        .and_then(|()| std::fs::OpenOptions::new().create(true).append(true).open(&path));
+    match file {
+        Ok(file) => Some(file),
+        Err(e) => {
+            eprintln!("Failed to open the log file {path:?}, logging to the terminal only: {e}");
+            None
+        }
+    }
 }
 
 #[inline]
 pub fn append_log(v: String) {
-    FILE.lock().unwrap().write(v.as_bytes()).unwrap();
+    append(&FILE, &v);
+}
+
+fn append(file: &Mutex<Option<File>>, v: &str) {
+    let mut file = file.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(writer) = file.as_mut() else { return };
+    if let Err(e) = writer.write_all(v.as_bytes()) {
+        eprintln!("Failed to write to the log file, logging to the terminal only: {e}");
+        *file = None;
+    }
+}
+
+fn push_recent(level: Level, message: &str) {
+    let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+    for line in message.lines() {
+        if recent.len() == RECENT_LINES { recent.pop_front(); }
+        recent.push_back((level, line.to_string()));
+    }
+}
+
+/// The last `count` lines logged, oldest first.
+pub fn recent_log(count: usize) -> Vec<(Level, String)> {
+    let recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+    recent.iter().skip(recent.len().saturating_sub(count)).cloned().collect()
+}
+
+pub fn clear_recent_log() {
+    RECENT.lock().unwrap_or_else(PoisonError::into_inner).clear();
 }
 
+/// The frames of the engine and game on the current stack, by symbol name so installed builds without the
+/// sources still show them.
 pub fn get_backtrace() -> String {
-    let cur_dir = match current_dir() { Ok(v) => v, Err(e) => return e.to_string() };
     let mut res = String::new();
     for frame in backtrace::Backtrace::new().frames() {
-        let symbol = &frame.symbols()[0];
-        if let Some(path) = symbol.filename() {
-            if !path.starts_with(&cur_dir) { continue }
-        } else { continue };
+        let Some(symbol) = frame.symbols().first() else { continue };
         let name = symbol.name().unwrap_or(SymbolName::new(&[])).to_string();
-        if name.starts_with("engine::logger") { continue; }
+        // Trait implementations start with <
+        let path = name.trim_start_matches('<');
+        if !(path.starts_with("engine::") || path.starts_with("d32::")) || path.starts_with("engine::utils::logger") || path.starts_with("engine::utils::crash") { continue }
         res.push_str(&format!("{}:{}\n", name, symbol.lineno().unwrap_or_default()));
     }
     res
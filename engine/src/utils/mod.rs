@@ -1,31 +1,14 @@
-use futures::executor::block_on;
 use winit::window::Window;
 
-use crate::settings::Settings;
+use crate::{EngineError, PresentMode, Settings, SurfaceFormat};
 
 mod smooth_value;  pub use smooth_value::*;
 mod cursor;        pub use cursor::*;
 mod logger;        pub use logger::*;
-
-pub fn create_adapter(instance: &wgpu::Instance, surface: &wgpu::Surface) -> wgpu::Adapter {
-    block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
-        compatible_surface: Some(&surface),
-        force_fallback_adapter: false
-    })).unwrap();
-    instance.enumerate_adapters(wgpu::Backends::all()).next().unwrap()
-}
-
-pub fn create_device_queue(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
-    block_on(adapter.request_device(
-        &wgpu::DeviceDescriptor {
-            features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
-            limits: wgpu::Limits::default(),
-            label: None
-        },
-        None
-    )).unwrap()
-}
+mod file_watcher;  pub use file_watcher::*;
+mod adapter;       pub use adapter::*;
+mod crash;         pub use crash::*;
+mod data_dir;      pub use data_dir::*;
 
 pub fn configure_surface(
     settings: &Settings,
@@ -33,20 +16,59 @@ pub fn configure_surface(
     device: &wgpu::Device,
     adapter: &wgpu::Adapter,
     surface: &wgpu::Surface
-) -> wgpu::SurfaceConfiguration {
+) -> Result<wgpu::SurfaceConfiguration, EngineError> {
     let size = window.inner_size();
+    let format = surface_format(settings.surface_format, &surface.get_supported_formats(adapter)).ok_or(EngineError::UnsupportedSurface)?;
+    let alpha_modes = surface.get_supported_alpha_modes(adapter);
     let config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: surface.get_supported_formats(adapter)[0],
+        format,
         width: size.width,
         height: size.height,
-        present_mode: if settings.vsync {
-            wgpu::PresentMode::AutoVsync
+        present_mode: present_mode(settings.present_mode, &surface.get_supported_present_modes(adapter)),
+        alpha_mode: if alpha_modes.contains(&wgpu::CompositeAlphaMode::Opaque) || alpha_modes.is_empty() {
+            wgpu::CompositeAlphaMode::Opaque
         } else {
-            wgpu::PresentMode::Immediate
-        },
-        alpha_mode: wgpu::CompositeAlphaMode::Opaque
+            alpha_modes[0]
+        }
     };
+    log::info!("Surface: {:?}, {:?}, {:?}", config.format, config.present_mode, config.alpha_mode);
     surface.configure(device, &config);
-    config
-}
\ No newline at end of file
+    Ok(config)
+}
+/// Picks the surface format closest to the requested one, falling back from HDR to sRGB and from there to
+/// whatever the surface offers first.
+pub fn surface_format(requested: SurfaceFormat, supported: &[wgpu::TextureFormat]) -> Option<wgpu::TextureFormat> {
+    let srgb = |format: &&wgpu::TextureFormat| format.describe().srgb;
+    let format = match requested {
+        SurfaceFormat::Hdr => supported.iter().find(|&&format| format == wgpu::TextureFormat::Rgba16Float).or_else(|| {
+            log::warn!("HDR output is not supported by this surface, using sRGB");
+            supported.iter().find(srgb)
+        }),
+        SurfaceFormat::Srgb => supported.iter().find(srgb),
+        SurfaceFormat::Linear => supported.iter().find(|format| !srgb(format))
+    };
+    format.or(supported.first()).copied()
+}
+/// Falls back to Fifo, which every surface supports, when the requested mode is not available.
+pub fn present_mode(requested: PresentMode, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    let mode = match requested {
+        PresentMode::Fifo => wgpu::PresentMode::Fifo,
+        PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+        PresentMode::Immediate => wgpu::PresentMode::Immediate
+    };
+    if supported.contains(&mode) { return mode }
+    // Mailbox is the closest to Immediate without tearing
+    let fallback = if mode == wgpu::PresentMode::Immediate && supported.contains(&wgpu::PresentMode::Mailbox) {
+        wgpu::PresentMode::Mailbox
+    } else {
+        wgpu::PresentMode::Fifo
+    };
+    log::warn!("Present mode {mode:?} is not supported by this surface, using {fallback:?}");
+    fallback
+}
+/// Whether colours written to `format` must be linear, because the hardware encodes sRGB or the format
+/// is floating point. The render target holds sRGB encoded colours.
+pub fn is_linear_output(format: wgpu::TextureFormat) -> bool {
+    format.describe().srgb || matches!(format, wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba32Float)
+}
@@ -0,0 +1,273 @@
+use std::{collections::HashMap, io, path::Path};
+
+use crate::{Chunk, Materials, CHUNK_SIZE};
+
+pub struct VoxModel {
+    pub size: [u32;3],
+    pub voxels: Vec<[u8;4]>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoxInstance {
+    pub model: usize,
+    pub translation: [i32;3],
+    pub rotation: [[i32;3];3]
+}
+
+pub struct VoxScene {
+    pub version: u32,
+    pub models: Vec<VoxModel>,
+    pub palette: [[u8;4];256],
+    pub instances: Vec<VoxInstance>
+}
+
+enum SceneNode {
+    Transform { child: u32, translation: [i32;3], rotation: [[i32;3];3] },
+    Group { children: Vec<u32> },
+    Shape { models: Vec<u32> }
+}
+
+const IDENTITY: [[i32;3];3] = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+impl VoxScene {
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read(path)?)
+    }
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        let mut r = Reader { bytes, offset: 0 };
+        if r.take(4)? != b"VOX " { return Err(invalid("missing VOX header")) }
+        let version = r.u32()?;
+        let (id, _, children) = r.chunk()?;
+        if id != *b"MAIN" { return Err(invalid("missing MAIN chunk")) }
+
+        let mut models = vec![];
+        let mut size = None;
+        let mut palette = default_palette();
+        let mut nodes = HashMap::new();
+        let mut r = Reader { bytes: children, offset: 0 };
+        while !r.is_empty() {
+            let (id, content, _) = r.chunk()?;
+            let mut c = Reader { bytes: content, offset: 0 };
+            match &id {
+                b"SIZE" => size = Some([c.u32()?, c.u32()?, c.u32()?]),
+                b"XYZI" => {
+                    let size = size.take().ok_or_else(|| invalid("XYZI chunk without a SIZE chunk"))?;
+                    let count = c.u32()? as usize;
+                    let voxels = (0..count).map(|_| Ok(c.take(4)?.try_into().unwrap())).collect::<io::Result<Vec<[u8;4]>>>()?;
+                    // Palette index 0 is reserved for empty space
+                    let voxels = voxels.into_iter().filter(|voxel| voxel[3] != 0).collect();
+                    models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => for i in 0..255 {
+                    palette[i + 1] = c.take(4)?.try_into().unwrap();
+                },
+                b"nTRN" => {
+                    let id = c.u32()?;
+                    c.dict()?;
+                    let child = c.u32()?;
+                    c.u32()?;
+                    c.u32()?;
+                    let frames = c.u32()?;
+                    let mut translation = [0;3];
+                    let mut rotation = IDENTITY;
+                    for frame in 0..frames {
+                        let attributes = c.dict()?;
+                        if frame != 0 { continue }
+                        if let Some(t) = attributes.get("_t") {
+                            let values: Vec<i32> = t.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+                            if values.len() == 3 { translation = [values[0], values[1], values[2]] }
+                        }
+                        if let Some(r) = attributes.get("_r") {
+                            rotation = decode_rotation(r.parse().map_err(|_| invalid("invalid rotation"))?)?;
+                        }
+                    }
+                    nodes.insert(id, SceneNode::Transform { child, translation, rotation });
+                }
+                b"nGRP" => {
+                    let id = c.u32()?;
+                    c.dict()?;
+                    let count = c.u32()?;
+                    let children = (0..count).map(|_| c.u32()).collect::<io::Result<_>>()?;
+                    nodes.insert(id, SceneNode::Group { children });
+                }
+                b"nSHP" => {
+                    let id = c.u32()?;
+                    c.dict()?;
+                    let count = c.u32()?;
+                    let mut shape_models = vec![];
+                    for _ in 0..count {
+                        shape_models.push(c.u32()?);
+                        c.dict()?;
+                    }
+                    nodes.insert(id, SceneNode::Shape { models: shape_models });
+                }
+                _ => {}
+            }
+        }
+
+        let mut instances = vec![];
+        if nodes.contains_key(&0) {
+            collect_instances(&nodes, 0, [0;3], IDENTITY, &mut instances, 0)?;
+        } else {
+            instances = (0..models.len()).map(|model| VoxInstance { model, translation: [0;3], rotation: IDENTITY }).collect();
+        }
+        if let Some(instance) = instances.iter().find(|i| i.model >= models.len()) {
+            return Err(invalid(&format!("scene references missing model {}", instance.model)))
+        }
+        Ok(Self { version, models, palette, instances })
+    }
+    pub fn voxels(&self) -> impl Iterator<Item = ([i32;3], u8)> + '_ {
+        self.instances.iter().flat_map(move |instance| {
+            let model = &self.models[instance.model];
+            let half = model.size.map(|v| (v / 2) as i32);
+            model.voxels.iter().map(move |[x, y, z, i]| {
+                let local = [*x as i32 - half[0], *y as i32 - half[1], *z as i32 - half[2]];
+                let mut position = instance.translation;
+                for (row, p) in instance.rotation.iter().zip(position.iter_mut()) {
+                    *p += row[0] * local[0] + row[1] * local[1] + row[2] * local[2];
+                }
+                (position, *i)
+            })
+        })
+    }
+    pub fn bounds(&self) -> Option<([i32;3], [i32;3])> {
+        self.voxels().fold(None, |bounds, (p, _)| Some(match bounds {
+            None => (p, p),
+            Some((min, max)) => ([0, 1, 2].map(|i| min[i].min(p[i])), [0, 1, 2].map(|i| max[i].max(p[i])))
+        }))
+    }
+    /// Places every model instance so that the minimum corner of the scene lands on `offset`, registering
+    /// the palette colours in use with `materials`.
+    pub fn to_chunks(&self, offset: [i32;3], materials: &mut Materials) -> Vec<Chunk> {
+        let Some((min, _)) = self.bounds() else { return vec![] };
+        let mut palette_materials = [None;256];
+        let mut chunks: HashMap<[i32;3], Chunk> = HashMap::new();
+        for (position, index) in self.voxels() {
+            let world = [0, 1, 2].map(|i| position[i] - min[i] + offset[i]);
+            let key = world.map(|v| v.div_euclid(CHUNK_SIZE as i32));
+            let [x, y, z] = world.map(|v| v.rem_euclid(CHUNK_SIZE as i32) as usize);
+            let material = *palette_materials[index as usize].get_or_insert_with(|| materials.add(self.palette[index as usize]));
+            chunks.entry(key).or_insert_with(|| {
+                let mut chunk = Chunk::new();
+                chunk.position = [key[0] as f32, key[1] as f32, key[2] as f32, 0.].map(|v| v * CHUNK_SIZE as f32);
+                chunk
+            }).data[x][y][z] = material;
+        }
+        let mut chunks: Vec<Chunk> = chunks.into_values().collect();
+        chunks.sort_by(|a, b| a.position.partial_cmp(&b.position).unwrap());
+        chunks
+    }
+}
+
+fn collect_instances(
+    nodes: &HashMap<u32, SceneNode>,
+    id: u32,
+    translation: [i32;3],
+    rotation: [[i32;3];3],
+    instances: &mut Vec<VoxInstance>,
+    depth: u32
+) -> io::Result<()> {
+    if depth > 64 { return Err(invalid("scene graph is too deep")) }
+    match nodes.get(&id) {
+        Some(SceneNode::Transform { child, translation: t, rotation: r }) => {
+            let mut world = translation;
+            for (row, w) in rotation.iter().zip(world.iter_mut()) {
+                *w += row[0] * t[0] + row[1] * t[1] + row[2] * t[2];
+            }
+            collect_instances(nodes, *child, world, multiply(rotation, *r), instances, depth + 1)
+        }
+        Some(SceneNode::Group { children }) => {
+            for child in children {
+                collect_instances(nodes, *child, translation, rotation, instances, depth + 1)?;
+            }
+            Ok(())
+        }
+        Some(SceneNode::Shape { models }) => {
+            instances.extend(models.iter().map(|model| VoxInstance { model: *model as usize, translation, rotation }));
+            Ok(())
+        }
+        None => Err(invalid(&format!("scene references missing node {id}")))
+    }
+}
+
+fn multiply(a: [[i32;3];3], b: [[i32;3];3]) -> [[i32;3];3] {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+// Bits 0-1 and 2-3 hold the column of the non-zero entry in the first and second rows, bits 4-6 the signs of the rows
+fn decode_rotation(bits: u8) -> io::Result<[[i32;3];3]> {
+    let first = (bits & 3) as usize;
+    let second = ((bits >> 2) & 3) as usize;
+    // The third row takes the remaining column, so the first two must be distinct columns
+    if first > 2 || second > 2 || first == second { return Err(invalid("invalid rotation")) }
+    let third = 3 - first - second;
+    let mut rotation = [[0;3];3];
+    for (row, column) in [first, second, third].into_iter().enumerate() {
+        rotation[row][column] = if bits & (1 << (4 + row)) != 0 { -1 } else { 1 };
+    }
+    Ok(rotation)
+}
+
+fn default_palette() -> [[u8;4];256] {
+    const STEPS: [u8;6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8;10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let mut palette = [[0;4];256];
+    let mut i = 1;
+    for r in STEPS {
+        for g in STEPS {
+            for b in STEPS {
+                if i < 216 { palette[i] = [r, g, b, 0xff] }
+                i += 1;
+            }
+        }
+    }
+    for channel in 0..3 {
+        for (j, v) in RAMP.into_iter().enumerate() {
+            let mut color = [0, 0, 0, 0xff];
+            color[channel] = v;
+            palette[216 + channel * 10 + j] = color;
+        }
+    }
+    for (j, v) in RAMP.into_iter().enumerate() {
+        palette[246 + j] = [v, v, v, 0xff];
+    }
+    palette
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid VOX file: {message}"))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize
+}
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        let end = self.offset.checked_add(length).filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| invalid("unexpected end of data"))?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn string(&mut self) -> io::Result<String> {
+        let length = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+    fn dict(&mut self) -> io::Result<HashMap<String, String>> {
+        let count = self.u32()?;
+        (0..count).map(|_| Ok((self.string()?, self.string()?))).collect()
+    }
+    fn chunk(&mut self) -> io::Result<([u8;4], &'a [u8], &'a [u8])> {
+        let id = self.take(4)?.try_into().unwrap();
+        let content_length = self.u32()? as usize;
+        let children_length = self.u32()? as usize;
+        Ok((id, self.take(content_length)?, self.take(children_length)?))
+    }
+}
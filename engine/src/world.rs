@@ -0,0 +1,60 @@
+use std::{io, path::Path};
+
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use crate::{Chunk, Heightmap, MaterialLayer, Materials, Region, Settings, VoxScene};
+
+// Generated worlds span this region unless another one is requested
+pub const WORLD_REGION: Region = Region { min: [0, 0, 0], max: [128, 128, 48] };
+const HEIGHTMAP_SCALE: f32 = 32.;
+
+/// Fills `region` with noise terrain, the same seed always giving the same world.
+pub fn generate_world(seed: u32, region: Region, materials: &mut Materials) -> Vec<Chunk> {
+    let [width, depth, height] = region.size().map(|v| v as u32);
+    if width == 0 || depth == 0 || height == 0 { return vec![] }
+    let noise = Fbm::<Perlin>::new(seed).set_octaves(5).set_frequency(1. / 96.);
+    let mut heights = Vec::with_capacity((width * depth) as usize);
+    for y in 0..depth {
+        for x in 0..width {
+            let n = noise.get([(region.min[0] + x as i32) as f64, (region.min[1] + y as i32) as f64]);
+            heights.push((((n * 0.5 + 0.5) * height as f64) as u32).min(height - 1));
+        }
+    }
+    let heightmap = Heightmap { size: [width, depth], heights, colors: None };
+    heightmap.to_chunks(region.min, &MaterialLayer::defaults(), materials)
+}
+
+/// Loads a world from a `.vox` model or a heightmap image.
+pub fn read_world(path: &Path, materials: &mut Materials) -> io::Result<Vec<Chunk>> {
+    match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("vox") => Ok(VoxScene::read(path)?.to_chunks([0;3], materials)),
+        Some("png" | "jpg" | "jpeg") => Ok(Heightmap::read(path, HEIGHTMAP_SCALE).map_err(io::Error::other)?
+            .to_chunks([0;3], &MaterialLayer::defaults(), materials)),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported world format, expected .vox or an image"))
+    }
+}
+
+/// The world file from the settings if there is one, otherwise terrain generated from the seed.
+pub fn load_world(settings: &Settings, materials: &mut Materials) -> Vec<Chunk> {
+    if let Some(path) = &settings.world_path {
+        match read_world(path, materials) {
+            Ok(chunks) => {
+                log::info!("Loaded {} chunks from {path:?}", chunks.len());
+                return chunks
+            }
+            Err(e) => log::error!("Failed to load world {path:?}, generating one instead: {e}")
+        }
+    }
+    let chunks = generate_world(settings.seed, WORLD_REGION, materials);
+    log::info!("Generated {} chunks with seed {}", chunks.len(), settings.seed);
+    chunks
+}
+
+/// A camera position and target looking across the whole of `chunks` from above one corner.
+pub fn overview(chunks: &[Chunk]) -> ([f32;3], [f32;3]) {
+    let Some(region) = Region::of_chunks(chunks) else { return ([0., -2., 0.], [0.;3]) };
+    let size = region.size().map(|v| v as f32);
+    let target = [0, 1, 2].map(|i| region.min[i] as f32 + size[i] / 2.);
+    let position = [region.min[0] as f32 - size[0] * 0.25, region.min[1] as f32 - size[1] * 0.25, region.max[2] as f32 + size[2]];
+    (position, target)
+}
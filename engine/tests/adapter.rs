@@ -0,0 +1,34 @@
+use engine::{score_adapter, Settings};
+
+fn info(name: &str, device_type: wgpu::DeviceType) -> wgpu::AdapterInfo {
+    wgpu::AdapterInfo {
+        name: name.into(),
+        vendor: 0,
+        device: 0,
+        device_type,
+        driver: String::new(),
+        driver_info: String::new(),
+        backend: wgpu::Backend::Vulkan
+    }
+}
+
+#[test]
+fn prefers_discrete_then_named_adapters() {
+    let settings = Settings::default();
+    let discrete = score_adapter(&info("GeForce", wgpu::DeviceType::DiscreteGpu), true, &settings).unwrap();
+    let integrated = score_adapter(&info("Intel UHD", wgpu::DeviceType::IntegratedGpu), true, &settings).unwrap();
+    assert!(discrete > integrated);
+    assert_eq!(score_adapter(&info("GeForce", wgpu::DeviceType::DiscreteGpu), false, &settings), None);
+
+    let settings = Settings { adapter_name: Some("intel".into()), ..Default::default() };
+    let discrete = score_adapter(&info("GeForce", wgpu::DeviceType::DiscreteGpu), true, &settings).unwrap();
+    let integrated = score_adapter(&info("Intel UHD", wgpu::DeviceType::IntegratedGpu), true, &settings).unwrap();
+    assert!(integrated > discrete);
+}
+
+#[test]
+fn forced_fallback_only_accepts_software_adapters() {
+    let settings = Settings { force_fallback_adapter: true, ..Default::default() };
+    assert_eq!(score_adapter(&info("GeForce", wgpu::DeviceType::DiscreteGpu), true, &settings), None);
+    assert!(score_adapter(&info("llvmpipe", wgpu::DeviceType::Cpu), true, &settings).is_some());
+}
@@ -0,0 +1,49 @@
+use engine::Chunk;
+
+// A chunk with the cube from 0 to `size` on each axis filled with `material`
+fn filled(size: usize, material: u32) -> Chunk {
+    let mut chunk = Chunk::new();
+    for plane in &mut chunk.data[..size] {
+        for row in &mut plane[..size] {
+            row[..size].fill(material);
+        }
+    }
+    chunk
+}
+
+#[test]
+fn full_chunk_keeps_its_material_at_every_level() {
+    let mut chunk = filled(16, 3);
+    chunk.generate_lods();
+    assert!(chunk.lod1.iter().flatten().flatten().all(|m| *m == 3));
+    assert!(chunk.lod2.iter().flatten().flatten().all(|m| *m == 3));
+    assert!(chunk.lod3.iter().flatten().flatten().all(|m| *m == 3));
+}
+
+#[test]
+fn cells_take_the_most_common_material_of_enough_solid_children() {
+    let mut chunk = Chunk::new();
+    // Three of one material and one of another
+    chunk.data[0][0][0] = 5;
+    chunk.data[1][0][0] = 5;
+    chunk.data[0][1][0] = 5;
+    chunk.data[1][1][1] = 6;
+    // Only three solid children
+    chunk.data[2][0][0] = 7;
+    chunk.data[3][0][0] = 7;
+    chunk.data[2][1][0] = 7;
+    chunk.generate_lods();
+    assert_eq!(chunk.lod1[0][0][0], 5);
+    assert_eq!(chunk.lod1[1][0][0], 0);
+}
+
+#[test]
+fn each_level_halves_the_resolution() {
+    let cases = [(2, [1, 0, 0]), (4, [1, 1, 0]), (8, [1, 1, 1])];
+    for (size, [lod1, lod2, lod3]) in cases {
+        let mut chunk = filled(size, 1);
+        chunk.generate_lods();
+        assert_eq!([chunk.lod1[0][0][0], chunk.lod2[0][0][0], chunk.lod3[0][0][0]], [lod1, lod2, lod3], "{size}");
+        assert_eq!(chunk.lod1.iter().flatten().flatten().filter(|m| **m != 0).count(), (size / 2).pow(3));
+    }
+}
@@ -0,0 +1,44 @@
+use engine::{parse_command, Console, HISTORY_LENGTH};
+
+#[test]
+fn splits_commands_into_name_and_arguments() {
+    assert_eq!(parse_command("tp 1 2.5 -3"), Some(("tp", vec!["1", "2.5", "-3"])));
+    assert_eq!(parse_command("  reload   shaders "), Some(("reload", vec!["shaders"])));
+    assert_eq!(parse_command("help"), Some(("help", vec![])));
+    assert_eq!(parse_command("   "), None);
+}
+
+#[test]
+fn browses_the_history() {
+    let console = Console::new();
+    console.browse_history(true);
+    assert_eq!(console.input(), "");
+    for line in ["help", "tp 0 0 0", "tp 0 0 0", "seed"] {
+        console.push_history(line);
+    }
+
+    console.browse_history(true);
+    assert_eq!(console.input(), "seed");
+    console.browse_history(true);
+    assert_eq!(console.input(), "tp 0 0 0");
+    console.browse_history(true);
+    console.browse_history(true);
+    assert_eq!(console.input(), "help");
+    console.browse_history(false);
+    assert_eq!(console.input(), "tp 0 0 0");
+    console.browse_history(false);
+    console.browse_history(false);
+    assert_eq!(console.input(), "");
+}
+
+#[test]
+fn caps_the_history() {
+    let console = Console::new();
+    for i in 0..HISTORY_LENGTH + 10 {
+        console.push_history(&format!("tp {i} 0 0"));
+    }
+    for _ in 0..HISTORY_LENGTH + 10 {
+        console.browse_history(true);
+    }
+    assert_eq!(console.input(), "tp 10 0 0");
+}
@@ -0,0 +1,36 @@
+use engine::{catch_device_loss, crash_report, crash_report_since};
+
+#[test]
+fn report_includes_the_panic_and_backtrace() {
+    let report = crash_report("panicked at src/main.rs:1:1: out of voxels", "0: d32::main");
+    assert!(report.starts_with("D32 "));
+    assert!(report.contains("OS: "));
+    assert!(report.contains("Adapter: none created"));
+    assert!(report.contains("Panic: panicked at src/main.rs:1:1: out of voxels"));
+    assert!(report.contains("Settings: not loaded"));
+    assert!(report.contains("Backtrace:\n0: d32::main"));
+}
+
+#[test]
+fn finds_reports_from_the_last_session() {
+    let dir = std::env::temp_dir().join(format!("d32-crashes-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in ["crash-20240101-120000.txt", "crash-20240102-100500.txt", "crash-20240102-100100.txt", "trace-20240102-110000.log"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+
+    let found = crash_report_since(&dir, "20240102-100000");
+    let none = crash_report_since(&dir, "20240102-110000");
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(found, Some(dir.join("crash-20240102-100500.txt")));
+    assert_eq!(none, None);
+}
+
+#[test]
+fn catches_only_device_loss_panics() {
+    assert_eq!(catch_device_loss(|| 1), Ok(1));
+    let lost = catch_device_loss(|| panic!("Error in Queue::submit: parent device is lost"));
+    assert_eq!(lost, Err("Error in Queue::submit: parent device is lost".to_string()));
+    let other = std::panic::catch_unwind(|| catch_device_loss(|| panic!("index out of bounds")));
+    assert!(other.is_err());
+}
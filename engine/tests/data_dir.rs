@@ -0,0 +1,24 @@
+use engine::{DataDirSource, DataDirs, PORTABLE_MARKER};
+
+#[test]
+fn override_comes_first() {
+    let dirs = DataDirs::resolve(Some("/games/d32".into()), None);
+    assert_eq!(dirs.source, DataDirSource::Override);
+    assert_eq!(dirs.config, std::path::Path::new("/games/d32"));
+    assert_eq!(dirs.data, dirs.config);
+    assert_eq!(dirs.cache, dirs.config.join("logs"));
+}
+
+#[test]
+fn portable_marker_keeps_data_next_to_the_executable() {
+    let exe_dir = std::env::temp_dir().join(format!("d32-portable-{}", std::process::id()));
+    std::fs::create_dir_all(&exe_dir).unwrap();
+    let installed = DataDirs::resolve(None, Some(&exe_dir));
+    std::fs::write(exe_dir.join(PORTABLE_MARKER), "").unwrap();
+    let portable = DataDirs::resolve(None, Some(&exe_dir));
+    std::fs::remove_dir_all(&exe_dir).unwrap();
+
+    assert_ne!(installed.source, DataDirSource::Portable);
+    assert_eq!(portable.source, DataDirSource::Portable);
+    assert_eq!(portable.config, exe_dir.join("data"));
+}
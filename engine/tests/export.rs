@@ -0,0 +1,52 @@
+use engine::{greedy_mesh, write_vox, Materials, Region, VoxScene, VoxelGrid};
+
+fn fixture(name: &str) -> VoxScene {
+    VoxScene::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)).unwrap()
+}
+
+#[test]
+fn vox_export_round_trips() {
+    let mut materials = Materials::new();
+    let chunks = fixture("scene.vox").to_chunks([0, 0, 0], &mut materials);
+    let grid = VoxelGrid::from_chunks(&chunks, Region::of_chunks(&chunks).unwrap());
+    let mut bytes = vec![];
+    write_vox(&mut bytes, &grid, &materials).unwrap();
+
+    let mut reimported_materials = Materials::new();
+    let reimported = VoxScene::parse(&bytes).unwrap().to_chunks([0, 0, 0], &mut reimported_materials);
+    assert_eq!(reimported.len(), chunks.len());
+    for (a, b) in chunks.iter().zip(&reimported) {
+        assert_eq!(a.position, b.position);
+        let colors = |chunk: &engine::Chunk, materials: &Materials| -> Vec<_> {
+            chunk.data.iter().flatten().flatten().map(|m| materials.get(*m)).collect()
+        };
+        assert_eq!(colors(a, &materials), colors(b, &reimported_materials));
+    }
+}
+
+#[test]
+fn greedy_mesh_merges_faces() {
+    let mut chunk = engine::Chunk::new();
+    for x in 0..4 {
+        for y in 0..2 {
+            chunk.data[x][y][0] = 1;
+        }
+    }
+    let grid = VoxelGrid::from_chunks(&[chunk], Region::new([0, 0, 0], [16, 16, 16]));
+    assert_eq!(greedy_mesh(&grid).len(), 6);
+}
+
+#[test]
+fn grid_is_clamped_to_the_chunks() {
+    let mut chunk = engine::Chunk::new();
+    chunk.position = [16., 0., -16., 0.];
+    chunk.data[0][0][0] = 1;
+    let grid = VoxelGrid::from_chunks(&[chunk], Region::new([-1_000_000; 3], [20, 1_000_000, 1_000_000]));
+    assert_eq!(grid.region, Region::new([16, 0, -16], [20, 16, 0]));
+    assert_eq!(grid.data.len(), 4 * 16 * 16);
+    assert_eq!(grid.get([0, 0, 0]), 1);
+
+    let outside = VoxelGrid::from_chunks(&[chunk], Region::new([100; 3], [200; 3]));
+    assert_eq!(outside.region.size(), [0, 0, 0]);
+    assert!(VoxelGrid::from_chunks(&[], Region::new([0; 3], [1_000_000; 3])).data.is_empty());
+}
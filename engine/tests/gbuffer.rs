@@ -0,0 +1,21 @@
+use engine::{unpad_rows, GBufferData};
+use winit::dpi::PhysicalSize;
+
+#[test]
+fn drops_row_padding() {
+    let padded = [1, 2, 3, 0, 0, 4, 5, 6, 0, 0];
+    assert_eq!(unpad_rows(&padded, 3, 5), [1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn converts_the_target_formats() {
+    let depth: Vec<u8> = [2.5f32, 1e30].iter().flat_map(|d| d.to_ne_bytes()).collect();
+    // 1, -1, 0.5 and 0 as half floats
+    let normal: Vec<u8> = [0x3c00u16, 0xbc00, 0x3800, 0x0000, 0, 0, 0, 0].iter().flat_map(|n| n.to_ne_bytes()).collect();
+    let material: Vec<u8> = [7u32, 0].iter().flat_map(|m| m.to_ne_bytes()).collect();
+
+    let data = GBufferData::from_bytes(PhysicalSize::new(2, 1), &depth, &normal, &material);
+    assert_eq!(data.depth, [2.5, 1e30]);
+    assert_eq!(data.normal, [[1., -1., 0.5, 0.], [0.;4]]);
+    assert_eq!(data.material, [7, 0]);
+}
@@ -0,0 +1,64 @@
+use engine::{palette_colors, Heightmap, MaterialLayer, Materials, MAX_SURFACE_COLORS};
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
+
+fn ramp() -> Heightmap {
+    // Heights 0, 10 and 20 along x with a vertical scale of 20
+    Heightmap::from_image(&DynamicImage::ImageLuma8(GrayImage::from_fn(3, 2, |x, _| Luma([(x * 255 / 2) as u8]))), 20.)
+}
+
+#[test]
+fn scales_heights() {
+    let heightmap = ramp();
+    assert_eq!(heightmap.size, [3, 2]);
+    assert_eq!(heightmap.heights, vec![0, 10, 20, 0, 10, 20]);
+}
+
+#[test]
+fn extrudes_columns_with_layers() {
+    let layers = [
+        MaterialLayer { depth: 1, color: [0, 255, 0, 255] },
+        MaterialLayer { depth: 2, color: [128, 64, 0, 255] },
+        MaterialLayer { depth: u32::MAX, color: [128, 128, 128, 255] }
+    ];
+    let mut materials = Materials::new();
+    let chunks = ramp().to_chunks([0, 0, 0], &layers, &mut materials);
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[1].position, [0., 0., 16., 0.]);
+
+    let column = |x: usize, z: usize| {
+        materials.get(chunks[z / 16].data[x][0][z % 16])
+    };
+    assert_eq!(column(0, 0), Some([0, 255, 0, 255]));
+    assert_eq!(column(0, 1), None);
+    assert_eq!(column(2, 20), Some([0, 255, 0, 255]));
+    assert_eq!(column(2, 19), Some([128, 64, 0, 255]));
+    assert_eq!(column(2, 18), Some([128, 64, 0, 255]));
+    assert_eq!(column(2, 17), Some([128, 128, 128, 255]));
+    assert_eq!(column(2, 0), Some([128, 128, 128, 255]));
+    assert_eq!(column(2, 21), None);
+}
+
+#[test]
+fn colour_map_paints_the_surface() {
+    let mut heightmap = ramp();
+    heightmap.set_colors(&DynamicImage::ImageRgb8(RgbImage::from_pixel(6, 4, Rgb([200, 10, 10]))));
+    let mut materials = Materials::new();
+    let chunks = heightmap.to_chunks([0, 0, 0], &MaterialLayer::defaults(), &mut materials);
+    assert_eq!(materials.get(chunks[0].data[1][1][10]), Some([200, 10, 10, 255]));
+    assert_eq!(materials.get(chunks[0].data[1][1][9]), Some(MaterialLayer::defaults()[1].color));
+}
+
+#[test]
+fn photographic_colour_maps_are_capped() {
+    let mut heightmap = ramp();
+    heightmap.heights = vec![0; 64 * 64];
+    heightmap.size = [64, 64];
+    heightmap.set_colors(&DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| Rgb([x as u8 * 4, y as u8 * 4, (x + y) as u8]))));
+    let mut materials = Materials::new();
+    heightmap.to_chunks([0, 0, 0], &MaterialLayer::defaults(), &mut materials);
+    assert!(materials.len() <= 1 + MaterialLayer::defaults().len() + MAX_SURFACE_COLORS, "{}", materials.len());
+
+    // Few enough colours are kept exactly
+    let colors = [[1, 2, 3, 0], [4, 5, 6, 255], [1, 2, 3, 255]];
+    assert_eq!(palette_colors(&colors), [[1, 2, 3, 255], [4, 5, 6, 255], [1, 2, 3, 255]]);
+}
@@ -0,0 +1,55 @@
+use engine::{build_filter, json_line, prune_logs};
+use log::{Level, MetadataBuilder, Record};
+use serde_json::Value;
+
+#[test]
+fn keeps_the_newest_logs() {
+    let dir = std::env::temp_dir().join(format!("d32-logs-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let names = ["trace-20240101-120000.log", "trace-20240102-090000.log", "trace-20240102-100000.log", "trace-20240101-120000.jsonl", "settings.json"];
+    for name in names {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+
+    prune_logs(&dir, 2).unwrap();
+    let mut remaining: Vec<String> = std::fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    remaining.sort();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(remaining, ["settings.json", "trace-20240101-120000.jsonl", "trace-20240102-090000.log", "trace-20240102-100000.log"]);
+}
+
+#[test]
+fn rust_log_replaces_the_settings_filter() {
+    let metadata = |target, level| MetadataBuilder::new().target(target).level(level).build();
+    let filter = build_filter("info,wgpu_core=warn", None);
+    assert!(filter.enabled(&metadata("engine", Level::Info)));
+    assert!(!filter.enabled(&metadata("engine", Level::Debug)));
+    assert!(!filter.enabled(&metadata("wgpu_core::device", Level::Info)));
+
+    let filter = build_filter("info,wgpu_core=warn", Some("trace"));
+    assert!(filter.enabled(&metadata("wgpu_core::device", Level::Trace)));
+    assert_eq!(build_filter("info", Some("")).filter(), log::LevelFilter::Info);
+}
+
+#[test]
+fn json_lines_carry_the_fields() {
+    let fields: &[(&str, &dyn log::kv::ToValue)] = &[("chunks", &12u32), ("ratio", &0.5f32), ("name", &"overworld"), ("lod", &true)];
+    let line = json_line(&Record::builder()
+        .args(format_args!("Loaded the world"))
+        .level(Level::Info)
+        .module_path(Some("engine::world"))
+        .line(Some(42))
+        .key_values(&fields)
+        .build());
+    assert!(line.ends_with('\n') && !line.trim_end().contains('\n'));
+
+    let json: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(json["level"], "INFO");
+    assert_eq!(json["module"], "engine::world");
+    assert_eq!(json["line"], 42);
+    assert_eq!(json["message"], "Loaded the world");
+    assert_eq!(json["fields"], serde_json::json!({ "chunks": 12, "ratio": 0.5, "name": "overworld", "lod": true }));
+    assert!(json["time"].as_str().unwrap().contains('T'));
+}
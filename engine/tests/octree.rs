@@ -0,0 +1,81 @@
+use engine::{Chunk, Octree, CHUNK_SIZE, OCTREE_LEAF};
+
+// Every stored node is the root or a child counted once in its parent's mask
+fn assert_node_count(octree: &Octree) {
+    let children: u32 = octree.nodes.iter()
+        .filter(|[word, _]| word & OCTREE_LEAF == 0)
+        .map(|[mask, _]| mask.count_ones())
+        .sum();
+    assert_eq!(octree.nodes.len(), children as usize + 1);
+}
+
+// A chunk at `position` with a mix of empty space, solid runs and scattered materials
+fn patterned(position: [f32;3], seed: usize) -> Chunk {
+    let mut chunk = Chunk::new();
+    chunk.position = [position[0], position[1], position[2], 0.];
+    for (x, plane) in chunk.data.iter_mut().enumerate() {
+        for (y, row) in plane.iter_mut().enumerate() {
+            for (z, material) in row.iter_mut().enumerate() {
+                *material = if z < 4 { 1 } else if (x * 7 + y * 13 + z * 5 + seed) % 11 == 3 { 2 + (x + seed) as u32 % 3 } else { 0 };
+            }
+        }
+    }
+    chunk
+}
+
+#[test]
+fn voxels_round_trip() {
+    let chunks = [patterned([0., 0., 0.], 0), patterned([-16., 0., 16.], 3), patterned([16., 16., 0.], 5)];
+    let octree = Octree::from_chunks(&chunks);
+    assert_node_count(&octree);
+    for chunk in &chunks {
+        let origin = chunk.position.map(|v| v as i32);
+        for (x, plane) in chunk.data.iter().enumerate() {
+            for (y, row) in plane.iter().enumerate() {
+                for (z, material) in row.iter().enumerate() {
+                    assert_eq!(octree.get([origin[0] + x as i32, origin[1] + y as i32, origin[2] + z as i32]), *material);
+                }
+            }
+        }
+    }
+    assert_eq!(octree.get([-17, 0, 0]), 0);
+    assert_eq!(Octree::empty().get([0, 0, 0]), 0);
+}
+
+#[test]
+fn uniform_regions_collapse() {
+    let mut full = Chunk::new();
+    full.data = [[[4;CHUNK_SIZE];CHUNK_SIZE];CHUNK_SIZE];
+    let octree = Octree::from_chunks(&[full]);
+    assert_eq!(octree.size, CHUNK_SIZE as u32);
+    assert_eq!(octree.nodes, [[OCTREE_LEAF | 4, 0]]);
+
+    // A single voxel needs a branch at each of the four halvings down to it
+    let mut single = Chunk::new();
+    single.data[0][0][0] = 2;
+    let octree = Octree::from_chunks(&[single]);
+    assert_node_count(&octree);
+    assert_eq!(octree.nodes.len(), 5);
+    assert_eq!(octree.get([0, 0, 0]), 2);
+    assert_eq!(octree.get([1, 0, 0]), 0);
+}
+
+#[test]
+fn branches_store_their_children_after_them() {
+    let mut chunk = Chunk::new();
+    chunk.data[0][0][0] = 2;
+    chunk.data[15][15][15] = 3;
+    let octree = Octree::from_chunks(&[chunk]);
+    // The root has the first and last octants, each stored depth first down to its leaf
+    assert_eq!(octree.nodes, [
+        [0b1000_0001, 1],
+        [0b0000_0001, 3],
+        [0b1000_0000, 6],
+        [0b0000_0001, 4],
+        [0b0000_0001, 5],
+        [OCTREE_LEAF | 2, 0],
+        [0b1000_0000, 7],
+        [0b1000_0000, 8],
+        [OCTREE_LEAF | 3, 0]
+    ]);
+}
@@ -0,0 +1,44 @@
+use engine::{RollingStats, Timings};
+
+#[test]
+fn rolling_stats_cover_the_last_samples() {
+    let mut stats = RollingStats::new(3);
+    assert_eq!(stats.last(), None);
+    for value in [10., 1., 2., 3.] {
+        stats.push(value);
+    }
+    assert_eq!(stats.len(), 3);
+    assert_eq!(stats.last(), Some(3.));
+    assert_eq!(stats.average(), 2.);
+    assert_eq!(stats.min(), 1.);
+    assert_eq!(stats.max(), 3.);
+}
+
+#[test]
+fn writes_a_column_per_stage() {
+    let mut timings = Timings::default();
+    timings.record("cpu update", 1.);
+    timings.end_frame();
+    timings.record("cpu update", 2.);
+    timings.record("gpu trace", 4.5);
+    timings.end_frame();
+
+    let mut csv = vec![];
+    timings.write_csv(&mut csv).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(), "frame,cpu update,gpu trace\n0,1.000,\n1,2.000,4.500\n");
+}
+
+#[test]
+fn late_results_go_to_the_frame_they_were_measured_in() {
+    let mut timings = Timings::default();
+    let measured = timings.frame_index();
+    timings.record("cpu update", 1.);
+    timings.end_frame();
+    timings.record("cpu update", 2.);
+    timings.record_in(measured, "gpu trace", 4.5);
+    timings.end_frame();
+
+    let mut csv = vec![];
+    timings.write_csv(&mut csv).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(), "frame,cpu update,gpu trace\n0,1.000,4.500\n1,2.000,\n");
+}
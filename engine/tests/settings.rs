@@ -0,0 +1,125 @@
+use engine::{PresentMode, RenderBackend, Settings, SETTINGS_VERSION};
+
+#[test]
+fn fills_missing_fields_with_defaults() {
+    let settings = Settings::parse(r#"{ "version": 2, "present_mode": "Mailbox", "render_backend": "Compute" }"#).unwrap();
+    assert_eq!(settings.present_mode, PresentMode::Mailbox);
+    assert_eq!(settings.render_backend, RenderBackend::Compute);
+    assert_eq!(settings.fov, Settings::default().fov);
+    assert_eq!(settings.lod_distance, Settings::default().lod_distance);
+}
+
+#[test]
+fn migrates_unversioned_files() {
+    let settings = Settings::parse(r#"{ "fov": 70.0, "some_removed_field": 1 }"#).unwrap();
+    assert_eq!(settings.version, SETTINGS_VERSION);
+    assert_eq!(settings.fov, 70.);
+}
+
+#[test]
+fn migrates_vsync_to_present_mode() {
+    let settings = Settings::parse(r#"{ "version": 1, "vsync": false }"#).unwrap();
+    assert_eq!(settings.present_mode, PresentMode::Immediate);
+    let settings = Settings::parse(r#"{ "vsync": true }"#).unwrap();
+    assert_eq!(settings.present_mode, PresentMode::Fifo);
+}
+
+#[test]
+fn migrates_window_position_to_signed() {
+    let settings = Settings::parse(r#"{ "version": 2, "window_position": [4294967000, 10] }"#).unwrap();
+    assert_eq!(settings.window_position, None);
+    let settings = Settings::parse(r#"{ "version": 2, "window_position": [100, 10] }"#).unwrap();
+    assert_eq!(settings.window_position, Some([100, 10]));
+    let settings = Settings::parse(r#"{ "window_position": [-1920, -40] }"#).unwrap();
+    assert_eq!(settings.window_position, Some([-1920, -40]));
+}
+
+#[test]
+fn hot_reload_keeps_the_other_running_fields() {
+    let running = Settings { seed: 42, window_size: Some([800, 600]), ..Default::default() };
+    let file = Settings {
+        fov: 70.,
+        present_mode: PresentMode::Immediate,
+        log_filter: "warn".into(),
+        seed: 1,
+        gbuffer: true,
+        ..Default::default()
+    };
+
+    let reloaded = running.with_hot_reloadable(&file);
+    assert_eq!(reloaded.fov, 70.);
+    assert_eq!(reloaded.present_mode, PresentMode::Immediate);
+    assert_eq!(reloaded.log_filter, "warn");
+    assert_eq!(reloaded.seed, 42);
+    assert_eq!(reloaded.window_size, Some([800, 600]));
+    assert!(!reloaded.gbuffer);
+}
+
+#[test]
+fn keeps_files_from_newer_versions() {
+    let settings = Settings::parse(&format!(r#"{{ "version": {}, "fov": 70.0, "added_later": true }}"#, SETTINGS_VERSION + 5)).unwrap();
+    assert_eq!(settings.version, SETTINGS_VERSION + 5);
+    assert_eq!(settings.fov, 70.);
+}
+
+#[test]
+fn clamps_out_of_range_values() {
+    let settings = Settings::parse(r#"{ "fov": 500.0, "window_size": [0, 720], "render_scale": 0.5, "min_render_scale": 0.9, "near": -1.0 }"#).unwrap();
+    assert_eq!(settings.fov, 179.);
+    assert_eq!(settings.window_size, None);
+    assert_eq!(settings.min_render_scale, 0.5);
+    assert!(settings.near > 0.);
+}
+
+#[test]
+fn uses_defaults_for_invalid_fields() {
+    let settings = Settings::parse(r#"{ "present_mode": "Sometimes", "fov": "wide", "seed": 7, "gbuffer": true }"#).unwrap();
+    assert_eq!(settings.present_mode, PresentMode::Fifo);
+    assert_eq!(settings.fov, 90.);
+    assert_eq!(settings.seed, 7);
+    assert!(settings.gbuffer);
+}
+
+#[test]
+fn rejects_invalid_files() {
+    assert!(Settings::parse(r#"{ "fov": 90.0,, }"#).is_err());
+    assert!(Settings::parse("[1, 2]").is_err());
+}
+
+#[test]
+fn sets_fields_by_name() {
+    let settings = Settings::default();
+    let changed = settings.with_field("fov", "100").unwrap();
+    assert_eq!(changed.fov, 100.);
+    assert_eq!(changed.field("fov"), Some(100.0.into()));
+    assert_eq!(settings.with_field("present_mode", "Mailbox").unwrap().present_mode, PresentMode::Mailbox);
+    assert_eq!(settings.with_field("fov", "1000").unwrap().fov, 179.);
+    assert!(settings.with_field("fov", "wide").is_err());
+    assert!(settings.with_field("colour", "1").is_err());
+}
+
+#[test]
+fn copies_only_the_named_fields() {
+    let file = Settings { fov: 75., seed: 1, ..Default::default() };
+    let running = Settings { fov: 100., seed: 42, render_scale: 0.5, ..Default::default() };
+    let merged = file.with_fields_from(&running, ["fov", "colour"]);
+    assert_eq!(merged.fov, 100.);
+    assert_eq!(merged.seed, 1);
+    assert_eq!(merged.render_scale, file.render_scale);
+}
+
+#[test]
+fn saves_and_reads_back() {
+    let dir = std::env::temp_dir().join(format!("d32-settings-{}", std::process::id()));
+    let path = dir.join("settings.json");
+    let mut settings = Settings { fov: 75., window_size: Some([800, 600]), ..Default::default() };
+    settings.save_to(&path).unwrap();
+    settings.fov = 60.;
+    settings.save_to(&path).unwrap();
+
+    let read = Settings::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(read.fov, 60.);
+    assert_eq!(read.window_size, Some([800, 600]));
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,21 @@
+use engine::{present_mode, surface_format, PresentMode, SurfaceFormat};
+use wgpu::TextureFormat;
+
+#[test]
+fn negotiates_surface_formats() {
+    let supported = [TextureFormat::Bgra8Unorm, TextureFormat::Bgra8UnormSrgb, TextureFormat::Rgba16Float];
+    assert_eq!(surface_format(SurfaceFormat::Srgb, &supported), Some(TextureFormat::Bgra8UnormSrgb));
+    assert_eq!(surface_format(SurfaceFormat::Linear, &supported), Some(TextureFormat::Bgra8Unorm));
+    assert_eq!(surface_format(SurfaceFormat::Hdr, &supported), Some(TextureFormat::Rgba16Float));
+    assert_eq!(surface_format(SurfaceFormat::Hdr, &supported[..2]), Some(TextureFormat::Bgra8UnormSrgb));
+    assert_eq!(surface_format(SurfaceFormat::Srgb, &supported[..1]), Some(TextureFormat::Bgra8Unorm));
+    assert_eq!(surface_format(SurfaceFormat::Srgb, &[]), None);
+}
+
+#[test]
+fn falls_back_to_supported_present_modes() {
+    let supported = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox];
+    assert_eq!(present_mode(PresentMode::Mailbox, &supported), wgpu::PresentMode::Mailbox);
+    assert_eq!(present_mode(PresentMode::Immediate, &supported), wgpu::PresentMode::Mailbox);
+    assert_eq!(present_mode(PresentMode::Immediate, &supported[..1]), wgpu::PresentMode::Fifo);
+}
@@ -0,0 +1,23 @@
+use engine::{text_size, Font, GLYPH_CELL};
+
+#[test]
+fn measures_text_by_lines() {
+    assert_eq!(text_size("", 1.), [0., 0.]);
+    assert_eq!(text_size("fps", 2.), [36., 16.]);
+    assert_eq!(text_size("a\nlonger\nb", 1.), [36., 24.]);
+}
+
+#[test]
+fn atlas_covers_printable_ascii() {
+    let font = Font::load();
+    assert_eq!(Font::cell(' '), [0, 0]);
+    assert_eq!(Font::cell('A'), [GLYPH_CELL[0], 2 * GLYPH_CELL[1]]);
+    assert_eq!(Font::cell('é'), Font::cell('?'));
+    let [x, y] = Font::cell('~');
+    assert!(x + GLYPH_CELL[0] <= font.image.width() && y + GLYPH_CELL[1] <= font.image.height());
+    // The solid cell follows the last glyph
+    let (min, max) = font.solid_uv();
+    assert_eq!(min, max);
+    let pixel = font.image.get_pixel((min[0] * font.image.width() as f32) as u32, (min[1] * font.image.height() as f32) as u32);
+    assert_eq!(pixel.0[3], 255);
+}
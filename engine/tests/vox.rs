@@ -0,0 +1,94 @@
+use engine::{Materials, VoxInstance, VoxScene};
+
+fn fixture(name: &str) -> VoxScene {
+    VoxScene::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)).unwrap()
+}
+
+#[test]
+fn reads_single_model_with_palette() {
+    let scene = fixture("single.vox");
+    assert_eq!(scene.version, 150);
+    assert_eq!(scene.models.len(), 1);
+    assert_eq!(scene.models[0].size, [2, 3, 4]);
+    assert_eq!(scene.models[0].voxels, vec![[0, 0, 0, 1], [1, 2, 3, 2], [1, 0, 3, 3]]);
+    assert_eq!(scene.palette[1], [255, 0, 0, 255]);
+    assert_eq!(scene.palette[3], [0, 0, 255, 128]);
+    assert_eq!(scene.instances.len(), 1);
+}
+
+#[test]
+fn converts_model_to_chunks_at_offset() {
+    let scene = fixture("single.vox");
+    let mut materials = Materials::new();
+    let chunks = scene.to_chunks([16, 0, 0], &mut materials);
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].position, [16., 0., 0., 0.]);
+    assert_eq!(materials.get(chunks[0].data[0][0][0]), Some([255, 0, 0, 255]));
+    assert_eq!(materials.get(chunks[0].data[1][2][3]), Some([0, 255, 0, 255]));
+    assert_eq!(materials.get(chunks[0].data[1][0][3]), Some([0, 0, 255, 128]));
+    assert_eq!(chunks[0].data.iter().flatten().flatten().filter(|m| **m != 0).count(), 3);
+}
+
+#[test]
+fn skips_voxels_with_the_empty_palette_index() {
+    let scene = fixture("empty_index.vox");
+    assert_eq!(scene.models[0].voxels, vec![[1, 0, 0, 5]]);
+    assert_eq!(scene.bounds(), Some(([0, 0, 0], [0, 0, 0])));
+    let mut materials = Materials::new();
+    let chunks = scene.to_chunks([0, 0, 0], &mut materials);
+    assert_eq!(chunks[0].data.iter().flatten().flatten().filter(|m| **m != 0).count(), 1);
+    // The empty material and palette colour 5
+    assert_eq!(materials.len(), 2);
+    assert_eq!(materials.get(chunks[0].data[0][0][0]), Some(scene.palette[5]));
+}
+
+#[test]
+fn reads_scene_graph_with_default_palette() {
+    let scene = fixture("scene.vox");
+    assert_eq!(scene.version, 200);
+    assert_eq!(scene.models.len(), 2);
+    assert_eq!(scene.instances, vec![
+        VoxInstance { model: 0, translation: [-10, 0, 1], rotation: [[1, 0, 0], [0, 1, 0], [0, 0, 1]] },
+        VoxInstance { model: 1, translation: [20, 5, 0], rotation: [[0, -1, 0], [1, 0, 0], [0, 0, 1]] }
+    ]);
+    assert_eq!(scene.palette[1], [0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(scene.palette[2], [0xff, 0xff, 0xcc, 0xff]);
+    assert_eq!(scene.palette[216], [0xee, 0, 0, 0xff]);
+    assert_eq!(scene.palette[255], [0x11, 0x11, 0x11, 0xff]);
+    assert_eq!(scene.bounds(), Some(([-11, -1, 0], [20, 6, 1])));
+}
+
+#[test]
+fn places_scene_instances_across_chunks() {
+    let scene = fixture("scene.vox");
+    let mut materials = Materials::new();
+    let chunks = scene.to_chunks([0, 0, 0], &mut materials);
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].position, [0., 0., 0., 0.]);
+    assert_eq!(chunks[1].position, [16., 0., 0., 0.]);
+    assert_eq!(materials.get(chunks[0].data[0][0][0]), Some([0xff, 0xff, 0xff, 0xff]));
+    assert_ne!(chunks[0].data[1][1][1], 0);
+    assert_eq!(materials.get(chunks[1].data[15][4][0]), Some([0xee, 0, 0, 0xff]));
+    assert_eq!(materials.get(chunks[1].data[15][7][0]), Some([0x11, 0x11, 0x11, 0xff]));
+}
+
+#[test]
+fn rejects_invalid_data() {
+    assert_eq!(VoxScene::parse(b"NOPE").err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+    let bytes = std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/single.vox")).unwrap();
+    assert!(VoxScene::parse(&bytes[..bytes.len() - 100]).is_err());
+}
+
+#[test]
+fn rejects_invalid_rotations() {
+    let bytes = std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/scene.vox")).unwrap();
+    let value = bytes.windows(8).position(|w| w == b"_r\x02\0\0\x0017").unwrap() + 6;
+    // A repeated column and a column past the third
+    for rotation in [b"10", b"15"] {
+        let mut bytes = bytes.clone();
+        bytes[value..value + 2].copy_from_slice(rotation);
+        let error = VoxScene::parse(&bytes).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("invalid rotation"));
+    }
+}
@@ -0,0 +1,30 @@
+use engine::{generate_world, Materials, Region};
+
+#[test]
+fn generation_is_deterministic_per_seed() {
+    let region = Region::new([0, 0, 0], [32, 32, 24]);
+    let generate = |seed| generate_world(seed, region, &mut Materials::new());
+    let (a, b, c) = (generate(1), generate(1), generate(2));
+    assert!(!a.is_empty());
+    assert!(a.iter().zip(&b).all(|(a, b)| a.position == b.position && a.data == b.data));
+    assert!(a.len() != c.len() || a.iter().zip(&c).any(|(a, c)| a.data != c.data));
+}
+
+#[test]
+fn generation_stays_inside_region() {
+    let region = Region::new([-16, 16, 0], [16, 48, 20]);
+    let chunks = generate_world(5, region, &mut Materials::new());
+    let bounds = Region::of_chunks(&chunks).unwrap();
+    assert_eq!([bounds.min[0], bounds.min[1]], [-16, 16]);
+    assert!(bounds.max[2] <= 32);
+    for chunk in &chunks {
+        for (x, plane) in chunk.data.iter().enumerate() {
+            for (y, row) in plane.iter().enumerate() {
+                for (z, material) in row.iter().enumerate() {
+                    if *material == 0 { continue }
+                    assert!(chunk.position[2] as i32 + (z as i32) < 20, "{x} {y} {z}");
+                }
+            }
+        }
+    }
+}